@@ -0,0 +1,197 @@
+//! Connect token issuing for secure netcode connections.
+//!
+//! In secure mode the server and the token issuer share a private key. The issuer hands every
+//! client a [`ConnectToken`] which pins its client id and name, so clients can no longer pick
+//! their own identity.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    time::SystemTime,
+};
+
+use bevy_replicon::renet::transport::{
+    generate_random_bytes, ConnectToken, TokenGenerationError, NETCODE_KEY_BYTES,
+};
+
+use crate::name_to_user_data;
+
+/// Private key shared by the server and the token issuer.
+pub type PrivateKey = [u8; NETCODE_KEY_BYTES];
+
+/// How long an issued token can be used to start a connection.
+pub const TOKEN_EXPIRE_SECONDS: u64 = 300;
+
+/// Seconds without packets after which a connection established with a token times out.
+const TOKEN_TIMEOUT_SECONDS: i32 = 15;
+
+/// Generates a new random private key.
+pub fn generate_private_key() -> PrivateKey {
+    generate_random_bytes()
+}
+
+/// Formats a private key as a lowercase hex string.
+pub fn private_key_to_hex(key: &PrivateKey) -> String {
+    key.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Parses a private key from the hex string produced by [`private_key_to_hex`].
+pub fn parse_private_key(hex: &str) -> Result<PrivateKey, String> {
+    let hex = hex.trim();
+    if hex.len() != NETCODE_KEY_BYTES * 2 {
+        return Err(format!(
+            "private key must be {} hex characters, got {}",
+            NETCODE_KEY_BYTES * 2,
+            hex.len()
+        ));
+    }
+
+    let mut key = [0; NETCODE_KEY_BYTES];
+    for (byte, chunk) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(chunk).map_err(|e| e.to_string())?;
+        *byte = u8::from_str_radix(digits, 16)
+            .map_err(|_| format!("invalid hex digits '{digits}' in private key"))?;
+    }
+
+    Ok(key)
+}
+
+/// Issues a connect token for `client_id` that is valid for the given server addresses.
+pub fn issue_connect_token(
+    private_key: &PrivateKey,
+    protocol_id: u64,
+    client_id: u64,
+    name: &str,
+    server_addresses: Vec<SocketAddr>,
+) -> Result<ConnectToken, TokenGenerationError> {
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("system time should be after the unix epoch");
+
+    ConnectToken::generate(
+        current_time,
+        protocol_id,
+        TOKEN_EXPIRE_SECONDS,
+        client_id,
+        TOKEN_TIMEOUT_SECONDS,
+        server_addresses,
+        Some(&name_to_user_data(name)),
+        private_key,
+    )
+}
+
+/// Writes a connect token to a file.
+pub fn write_connect_token(token: &ConnectToken, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    token.write(&mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a connect token written by [`write_connect_token`].
+pub fn read_connect_token(path: &Path) -> Result<ConnectToken, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let token = ConnectToken::read(&mut reader)?;
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, thread, time::Duration};
+
+    use bevy_replicon::renet::{
+        transport::{
+            ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport,
+            ServerAuthentication, ServerConfig,
+        },
+        ConnectionConfig, RenetClient, RenetServer,
+    };
+
+    use super::*;
+    use crate::PROTOCOL_ID;
+
+    fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+    }
+
+    /// Runs the netcode handshake against a secure server and returns whether the client got in.
+    fn connects(
+        private_key: PrivateKey,
+        protocol_id: u64,
+        server_time: Duration,
+        token: impl FnOnce(SocketAddr) -> ConnectToken,
+    ) -> bool {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let mut server_transport = NetcodeServerTransport::new(
+            ServerConfig {
+                current_time: server_time,
+                max_clients: 1,
+                protocol_id,
+                authentication: ServerAuthentication::Secure { private_key },
+                public_addresses: vec![server_addr],
+            },
+            socket,
+        )
+        .unwrap();
+        let mut server = RenetServer::new(ConnectionConfig::default());
+
+        let mut client_transport = NetcodeClientTransport::new(
+            now(),
+            ClientAuthentication::Secure {
+                connect_token: token(server_addr),
+            },
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+        )
+        .unwrap();
+        let mut client = RenetClient::new(ConnectionConfig::default());
+
+        let delta = Duration::from_millis(10);
+        for _ in 0..50 {
+            // Rejected tokens surface as transport errors, which only matter through the outcome.
+            let _ = client_transport.update(delta, &mut client);
+            let _ = client_transport.send_packets(&mut client);
+            thread::sleep(delta);
+            let _ = server_transport.update(delta, &mut server);
+            server_transport.send_packets(&mut server);
+            thread::sleep(delta);
+            if client.is_connected() {
+                return server_transport.connected_clients() == 1;
+            }
+        }
+
+        false
+    }
+
+    #[test]
+    fn issued_token_connects() {
+        let key = generate_private_key();
+        assert!(connects(key, PROTOCOL_ID, now(), |addr| {
+            issue_connect_token(&key, PROTOCOL_ID, 7, "Alice", vec![addr]).unwrap()
+        }));
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let key = generate_private_key();
+        let later = now() + Duration::from_secs(TOKEN_EXPIRE_SECONDS + 1);
+        assert!(!connects(key, PROTOCOL_ID, later, |addr| {
+            issue_connect_token(&key, PROTOCOL_ID, 7, "Alice", vec![addr]).unwrap()
+        }));
+    }
+
+    #[test]
+    fn token_for_another_protocol_is_rejected() {
+        let key = generate_private_key();
+        assert!(!connects(key, PROTOCOL_ID, now(), |addr| {
+            issue_connect_token(&key, PROTOCOL_ID + 1, 7, "Alice", vec![addr]).unwrap()
+        }));
+    }
+}
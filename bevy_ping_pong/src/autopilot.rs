@@ -0,0 +1,214 @@
+//! Computer control of the local paddle, for clients that run without a player.
+//!
+//! The [`AutoPilotPlugin`] marks the client ready whenever it's in the lobby, steers its paddle
+//! like a server bot of the chosen [`BotDifficulty`] and leaves every finished match to be paired
+//! again. Its inputs go through the [`InputHistory`] like the keyboard inputs, so the paddle is
+//! predicted the same way.
+//!
+//! Clients don't receive the velocity of the ball, the autopilot derives it from the replicated
+//! positions of two consecutive server ticks. The outcomes of the finished matches are counted in
+//! [`AutoPilotStats`].
+
+use bevy::prelude::*;
+
+use crate::{
+    bot::{BotDifficulty, Steering},
+    lobby::Lobby,
+    prediction::{InputHistory, PredictedPosition},
+    room::{CurrentRoom, RoomId},
+    rules::MatchRules,
+    scoring::Score,
+    tick::{SimulationTick, TickRate},
+    Ball, C2sMessage, ClientMessage, GameData, GameState, LocalData, MoveDirection, Paddle, Player,
+    PlayerPosition,
+};
+
+/// Plays the matches of a client without input, see the module docs.
+///
+/// Needs the [`PingPongSharedPlugin`](crate::PingPongSharedPlugin) and the
+/// [`PingPongClientPlugin`](crate::PingPongClientPlugin) and expects the [`LocalData`] to be
+/// inserted before the app starts.
+pub struct AutoPilotPlugin {
+    pub difficulty: BotDifficulty,
+}
+
+impl Plugin for AutoPilotPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AutoPilot {
+            steering: Steering::new(self.difficulty, rand::random()),
+            ticks: 0,
+            last_ball: None,
+            ball_velocity: Vec2::ZERO,
+            ready_sent: false,
+        })
+        .init_resource::<AutoPilotStats>()
+        .add_systems(FixedUpdate, steer_system.run_if(in_state(GameState::Game)))
+        .add_systems(OnEnter(GameState::End), leave_system)
+        .add_systems(Update, (ready_system, track_ball_system));
+    }
+}
+
+/// Outcomes of the matches the autopilot finished.
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct AutoPilotStats {
+    pub won: u32,
+    pub lost: u32,
+    pub drawn: u32,
+}
+
+impl AutoPilotStats {
+    pub fn played(&self) -> u32 {
+        self.won + self.lost + self.drawn
+    }
+}
+
+#[derive(Resource)]
+struct AutoPilot {
+    steering: Steering,
+    /// Ticks steered so far, counts the reaction time of the steering.
+    ticks: u32,
+    /// Server time and position of the ball in the last received tick.
+    last_ball: Option<(f64, Vec2)>,
+    ball_velocity: Vec2,
+    /// The client asked to be ready and waits for the lobby to confirm it.
+    ready_sent: bool,
+}
+
+/// Enters the matchmaking queue whenever the client waits in the lobby.
+fn ready_system(
+    local_data: Res<LocalData>,
+    current_room: Res<CurrentRoom>,
+    lobby: Res<Lobby>,
+    mut autopilot: ResMut<AutoPilot>,
+    mut client_messages: EventWriter<ClientMessage>,
+) {
+    let member = lobby.member(local_data.client_id);
+    if current_room.0.is_some() || member.is_none_or(|member| member.ready) {
+        autopilot.ready_sent = false;
+        return;
+    }
+    if autopilot.ready_sent || lobby.is_paired(local_data.client_id) {
+        return;
+    }
+
+    autopilot.ready_sent = true;
+    client_messages.send(ClientMessage {
+        msg: C2sMessage::SetReady(true),
+    });
+}
+
+/// Estimates the velocity of the ball in the current room from the last two server ticks.
+fn track_ball_system(
+    current_room: Res<CurrentRoom>,
+    mut autopilot: ResMut<AutoPilot>,
+    ticks: Query<&SimulationTick, Changed<SimulationTick>>,
+    balls: Query<(&RoomId, &PlayerPosition), With<Ball>>,
+) {
+    let Some(tick) = ticks.iter().last() else {
+        return;
+    };
+    let Some((_, ball)) = balls
+        .iter()
+        .find(|(room_id, _)| current_room.0 == Some(**room_id))
+    else {
+        autopilot.last_ball = None;
+        return;
+    };
+
+    let server_time = tick.seconds();
+    if let Some((last_time, last_position)) = autopilot.last_ball {
+        let elapsed = (server_time - last_time) as f32;
+        if elapsed > 0.0 {
+            autopilot.ball_velocity = (**ball - last_position) / elapsed;
+        }
+    }
+    autopilot.last_ball = Some((server_time, **ball));
+}
+
+/// Sends the input of the steering once per simulation tick.
+fn steer_system(
+    rules: Res<MatchRules>,
+    tick_rate: Res<TickRate>,
+    game_data: Res<GameData>,
+    local_data: Res<LocalData>,
+    current_room: Res<CurrentRoom>,
+    mut autopilot: ResMut<AutoPilot>,
+    mut history: ResMut<InputHistory>,
+    mut move_events: EventWriter<MoveDirection>,
+    mut paddles: Query<
+        (
+            &Player,
+            &RoomId,
+            &PlayerPosition,
+            Option<&mut PredictedPosition>,
+        ),
+        With<Paddle>,
+    >,
+) {
+    if game_data.is_spectator(local_data.client_id) {
+        return;
+    }
+    let Some((_, ball)) = autopilot.last_ball else {
+        return;
+    };
+    let Some((_, _, position, predicted)) = paddles.iter_mut().find(|(player, room_id, ..)| {
+        player.0.raw() == local_data.client_id && current_room.0 == Some(**room_id)
+    }) else {
+        return;
+    };
+
+    let autopilot = &mut *autopilot;
+    autopilot.ticks += 1;
+    let paddle = predicted
+        .as_deref()
+        .map_or(**position, |predicted| **predicted);
+    let Some(input) = autopilot.steering.input(
+        autopilot.ticks,
+        &rules,
+        *tick_rate,
+        paddle,
+        ball,
+        autopilot.ball_velocity,
+    ) else {
+        return;
+    };
+
+    let direction = Vec2::new(0.0, input);
+    let sequence = history.record(direction);
+    move_events.send(MoveDirection {
+        direction,
+        sequence,
+    });
+    if let Some(mut predicted) = predicted {
+        **predicted = rules.move_paddle(**predicted, direction, tick_rate.duration());
+    }
+}
+
+/// Counts the outcome of the finished match and goes back to the lobby.
+fn leave_system(
+    game_data: Res<GameData>,
+    local_data: Res<LocalData>,
+    current_room: Res<CurrentRoom>,
+    mut stats: ResMut<AutoPilotStats>,
+    mut client_messages: EventWriter<ClientMessage>,
+    scores: Query<(&RoomId, &Score)>,
+) {
+    if game_data.is_spectator(local_data.client_id) {
+        return;
+    }
+    let Some((_, score)) = scores
+        .iter()
+        .find(|(room_id, _)| current_room.0 == Some(**room_id))
+    else {
+        return;
+    };
+
+    match game_data.winner(score) {
+        Some(winner) if winner == local_data.client_id => stats.won += 1,
+        Some(_) => stats.lost += 1,
+        None => stats.drawn += 1,
+    }
+    client_messages.send(ClientMessage {
+        msg: C2sMessage::Leave,
+    });
+}
@@ -0,0 +1,283 @@
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    process, thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::{
+    asset::AssetPlugin, gizmos::GizmoPlugin, input::InputPlugin, prelude::*,
+    render::render_resource::Shader,
+};
+use bevy_ping_pong::{
+    autopilot::{AutoPilotPlugin, AutoPilotStats},
+    bot::{BotDifficulty, FIRST_BOT_ID},
+    name_to_user_data, LocalData, PingPongClientPlugin, PingPongSharedPlugin, PORT, PROTOCOL_ID,
+};
+use bevy_replicon::replicon_core::NetworkChannels;
+use bevy_replicon::{
+    prelude::*,
+    renet::{
+        transport::{ClientAuthentication, NetcodeClientTransport},
+        ConnectionConfig,
+    },
+};
+use clap::Parser;
+use rand::Rng;
+
+/// Updates of every connection per second.
+const FRAME_RATE: f64 = 60.0;
+
+/// Headless clients that play on their own, to put load on a server.
+///
+/// Every connection readies up in the lobby, plays its matches and goes back to the lobby. The
+/// server has to be started with --unsecure.
+#[derive(Debug, Parser)]
+#[command(name = "bot", about)]
+struct BotArgs {
+    /// Number of connections opened by this process.
+    #[arg(
+        short,
+        long,
+        env = "PING_PONG_BOT_COUNT",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    count: u16,
+
+    /// Host name or IP address of the server.
+    #[arg(short, long, env = "PING_PONG_SERVER", default_value = "127.0.0.1")]
+    server: String,
+
+    /// UDP port of the server.
+    #[arg(short, long, env = "PING_PONG_PORT", default_value_t = PORT)]
+    port: u16,
+
+    /// Netcode protocol id, must match the server.
+    #[arg(long, env = "PING_PONG_PROTOCOL_ID", default_value_t = PROTOCOL_ID)]
+    protocol_id: u64,
+
+    /// How well the connections play: easy, medium or hard.
+    #[arg(short, long, env = "PING_PONG_BOT_DIFFICULTY", default_value_t = BotDifficulty::Hard)]
+    difficulty: BotDifficulty,
+
+    /// Seconds between two reports.
+    #[arg(
+        long,
+        env = "PING_PONG_BOT_REPORT_INTERVAL",
+        default_value = "10",
+        value_parser = parse_secs
+    )]
+    report_interval: Duration,
+
+    /// Seconds after which all connections disconnect and the process exits, runs until
+    /// interrupted when omitted.
+    #[arg(long, env = "PING_PONG_BOT_DURATION", value_parser = parse_secs)]
+    duration: Option<Duration>,
+}
+
+/// Parses a positive, finite number of seconds.
+fn parse_secs(arg: &str) -> Result<Duration, String> {
+    let secs: f32 = arg.parse().map_err(|e| format!("{e}"))?;
+    match Duration::try_from_secs_f32(secs) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(format!("expected a positive number of seconds, got {arg}")),
+    }
+}
+
+impl BotArgs {
+    fn server_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        let host = (self.server.as_str(), self.port);
+        let server_addr = host
+            .to_socket_addrs()
+            .map_err(|e| format!("unable to resolve {}: {e}", self.server))?
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", self.server))?;
+
+        Ok(server_addr)
+    }
+}
+
+/// A headless client app with its own connection to the server.
+struct Connection {
+    app: App,
+    client_id: u64,
+}
+
+impl Connection {
+    fn new(args: &BotArgs, server_addr: SocketAddr) -> Result<Self, Box<dyn Error>> {
+        // Id 0 is reserved for the server itself, the highest ids for bots.
+        let client_id = rand::thread_rng().gen_range(1..FIRST_BOT_ID);
+        let transport = create_transport(args, server_addr, client_id)?;
+
+        let mut app = App::new();
+        // The client plugin reads the keyboard and draws gizmos, neither of which is shown.
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), InputPlugin))
+            .init_asset::<Shader>()
+            .add_plugins((
+                GizmoPlugin,
+                ReplicationPlugins.build().set(ServerPlugin {
+                    tick_policy: TickPolicy::Manual,
+                    ..Default::default()
+                }),
+            ))
+            .add_plugins((
+                PingPongSharedPlugin,
+                PingPongClientPlugin,
+                AutoPilotPlugin {
+                    difficulty: args.difficulty,
+                },
+            ))
+            .insert_resource(transport)
+            .insert_resource(LocalData { client_id })
+            .add_systems(Startup, init_client);
+
+        Ok(Self { app, client_id })
+    }
+
+    fn report(&self) -> String {
+        let world = &self.app.world;
+        let stats = world.resource::<AutoPilotStats>();
+        let outcomes = format!(
+            "{} matches: {} won, {} lost, {} drawn",
+            stats.played(),
+            stats.won,
+            stats.lost,
+            stats.drawn
+        );
+        let status = match (
+            world.get_resource::<RenetClient>(),
+            world
+                .resource::<NetcodeClientTransport>()
+                .disconnect_reason(),
+        ) {
+            (_, Some(reason)) => format!("disconnected: {reason}"),
+            (Some(client), None) if client.is_disconnected() => match client.disconnect_reason() {
+                Some(reason) => format!("disconnected: {reason}"),
+                None => "disconnected".to_string(),
+            },
+            (Some(client), None) if client.is_connected() => {
+                let info = client.network_info();
+                format!(
+                    "rtt {:.1} ms, packet loss {:.1} %",
+                    info.rtt * 1000.0,
+                    info.packet_loss * 100.0
+                )
+            }
+            _ => "connecting".to_string(),
+        };
+
+        format!("{:>20}: {status}, {outcomes}", self.client_id)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        let world = &self.app.world;
+        world
+            .resource::<NetcodeClientTransport>()
+            .disconnect_reason()
+            .is_some()
+            || world
+                .get_resource::<RenetClient>()
+                .is_some_and(|client| client.is_disconnected())
+    }
+
+    fn disconnect(&mut self) {
+        self.app
+            .world
+            .resource_mut::<NetcodeClientTransport>()
+            .disconnect();
+    }
+}
+
+fn main() {
+    let args = BotArgs::parse();
+    let server_addr = args.server_addr().unwrap_or_else(|e| exit_with_error(e));
+    println!(
+        "connecting {} {} bots to {server_addr}",
+        args.count, args.difficulty
+    );
+    let mut connections: Vec<_> = (0..args.count)
+        .map(|_| Connection::new(&args, server_addr))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| exit_with_error(e));
+
+    let frame = Duration::from_secs_f64(1.0 / FRAME_RATE);
+    let started = Instant::now();
+    let mut last_report = started;
+    loop {
+        let frame_start = Instant::now();
+        for connection in &mut connections {
+            connection.app.update();
+        }
+
+        if args
+            .duration
+            .is_some_and(|duration| started.elapsed() >= duration)
+        {
+            break;
+        }
+        if last_report.elapsed() >= args.report_interval {
+            last_report = Instant::now();
+            print_report(&connections, started);
+        }
+        thread::sleep(frame.saturating_sub(frame_start.elapsed()));
+    }
+
+    print_report(&connections, started);
+    let dropped = connections
+        .iter()
+        .filter(|connection| connection.is_disconnected())
+        .count();
+    for connection in &mut connections {
+        connection.disconnect();
+    }
+    if dropped > 0 {
+        eprintln!("error: {dropped} of {} connections were lost", args.count);
+        process::exit(1);
+    }
+}
+
+fn print_report(connections: &[Connection], started: Instant) {
+    println!("after {} s:", started.elapsed().as_secs());
+    for connection in connections {
+        println!("{}", connection.report());
+    }
+}
+
+fn exit_with_error(e: Box<dyn Error>) -> ! {
+    eprintln!("error: {e}");
+    process::exit(1);
+}
+
+fn create_transport(
+    args: &BotArgs,
+    server_addr: SocketAddr,
+    client_id: u64,
+) -> Result<NetcodeClientTransport, Box<dyn Error>> {
+    let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let name = format!("Bot {client_id}");
+    let authentication = ClientAuthentication::Unsecure {
+        client_id,
+        protocol_id: args.protocol_id,
+        server_addr,
+        user_data: Some(name_to_user_data(&name)),
+    };
+    let local_ip = match server_addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((local_ip, 0))?;
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket)?;
+
+    Ok(transport)
+}
+
+fn init_client(mut commands: Commands, network_channels: Res<NetworkChannels>) {
+    let client = RenetClient::new(ConnectionConfig {
+        server_channels_config: network_channels.get_server_configs(),
+        client_channels_config: network_channels.get_client_configs(),
+        ..Default::default()
+    });
+
+    commands.insert_resource(client);
+}
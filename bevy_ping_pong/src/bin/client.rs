@@ -1,11 +1,22 @@
 use std::{
     error::Error,
-    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    path::PathBuf,
+    process,
     time::SystemTime,
 };
 
-use bevy::{app::ScheduleRunnerPlugin, prelude::*, utils::Duration, sprite::MaterialMesh2dBundle};
-use bevy_egui::EguiPlugin;
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Align2},
+    EguiContext, EguiPlugin,
+};
+use bevy_ping_pong::{
+    auth::read_connect_token, bot::FIRST_BOT_ID, hot_seat::HotSeatPlugin,
+    interpolation::InterpolationSettings, name_to_user_data, playback::ReplayPlaybackPlugin,
+    replay::Replay, room::Rooms, HostPlayer, LocalData, PingPongClientPlugin, PingPongServerPlugin,
+    PingPongSharedPlugin, PORT, PROTOCOL_ID,
+};
 use bevy_replicon::replicon_core::NetworkChannels;
 use bevy_replicon::{
     prelude::*,
@@ -14,73 +25,358 @@ use bevy_replicon::{
             ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport,
             ServerAuthentication, ServerConfig,
         },
-        ClientId, ConnectionConfig, ServerEvent,
+        ConnectionConfig,
     },
 };
-use bevy_ping_pong::{PlayerBundle, PingPongPlugin, PORT, PROTOCOL_ID, LocalData};
+use clap::{ArgGroup, Parser};
+use rand::Rng;
 
-fn main() {
-    App::new()
-        .add_plugins((DefaultPlugins, ReplicationPlugins))
-        .add_plugins(PingPongPlugin)
-        .add_plugins(EguiPlugin)
-        .add_systems(Startup, init_client.map(Result::unwrap))
-        .add_systems(Update, bevy_ping_pong::PingPongPlugin::render_gui_client)
-        .run();
+/// Remote players and spectators a hosted game accepts.
+const HOST_MAX_CLIENTS: usize = 8;
+
+/// Netcode refuses more than this many public addresses.
+const MAX_PUBLIC_ADDRESSES: usize = 32;
+
+/// Ping pong game client.
+///
+/// Without --token, --unsecure, --host, --offline or --replay a start screen offers to host a
+/// game or to join one.
+#[derive(Clone, Debug, Parser)]
+#[command(name = "client", about)]
+#[command(group(ArgGroup::new("authentication")))]
+struct ClientArgs {
+    /// Connect token issued by the `token` binary. The server address, client id and name
+    /// are taken from the token.
+    #[arg(short, long, env = "PING_PONG_TOKEN", group = "authentication")]
+    token: Option<PathBuf>,
+
+    /// Connect without a token, for servers started with --unsecure.
+    #[arg(long, group = "authentication")]
+    unsecure: bool,
+
+    /// Host a game instead of connecting to a server. The game is played in this process and
+    /// listens on --server and --port for other players, who join with --unsecure.
+    #[arg(long, group = "authentication")]
+    host: bool,
+
+    /// Play without a network, both players share the keyboard. The left paddle moves with W
+    /// and S, the right one with the arrow keys.
+    #[arg(long, group = "authentication")]
+    offline: bool,
+
+    /// Play back a replay recorded by a server started with --replay-dir. Space pauses, the left
+    /// and right arrow keys seek and the up and down arrow keys change the speed.
+    #[arg(long, value_name = "FILE", group = "authentication")]
+    replay: Option<PathBuf>,
+
+    /// Host name or IP address of the server, or the address to listen on with --host.
+    #[arg(short, long, env = "PING_PONG_SERVER", default_value = "127.0.0.1")]
+    server: String,
+
+    /// UDP port of the server.
+    #[arg(short, long, env = "PING_PONG_PORT", default_value_t = PORT)]
+    port: u16,
+
+    /// Address other players connect to when hosting, may be repeated or comma separated.
+    /// Defaults to --server and --port.
+    #[arg(
+        long = "public-addr",
+        env = "PING_PONG_PUBLIC_ADDRS",
+        value_delimiter = ','
+    )]
+    public_addrs: Vec<SocketAddr>,
+
+    /// Netcode protocol id, must match the server.
+    #[arg(long, env = "PING_PONG_PROTOCOL_ID", default_value_t = PROTOCOL_ID)]
+    protocol_id: u64,
+
+    /// Client id to connect with, a random id is used when omitted.
+    #[arg(
+        long,
+        env = "PING_PONG_CLIENT_ID",
+        conflicts_with_all = ["token", "host", "offline", "replay"],
+        value_parser = clap::value_parser!(u64).range(1..FIRST_BOT_ID)
+    )]
+    client_id: Option<u64>,
+
+    /// Name shown to other players.
+    #[arg(short, long, env = "PING_PONG_NAME", conflicts_with = "token")]
+    name: Option<String>,
+
+    /// How far in the past the opponent and the ball are drawn, in milliseconds. Higher values
+    /// hide more network jitter.
+    #[arg(long, env = "PING_PONG_INTERPOLATION_DELAY", default_value_t = 100)]
+    interpolation_delay: u32,
 }
 
-fn init_client(
-    mut commands: Commands,
-    network_channels: Res<NetworkChannels>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>
-) -> Result<(), Box<dyn Error>> {
-    const ip: IpAddr = std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-    let server_channels_config = network_channels.get_server_configs();
-    let client_channels_config = network_channels.get_client_configs();
-
-    let client = RenetClient::new(ConnectionConfig {
-        server_channels_config,
-        client_channels_config,
+impl ClientArgs {
+    fn server_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        let host = (self.server.as_str(), self.port);
+        let server_addr = host
+            .to_socket_addrs()
+            .map_err(|e| format!("unable to resolve {}: {e}", self.server))?
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", self.server))?;
+
+        Ok(server_addr)
+    }
+
+    /// Returns the addresses advertised to the other players of a hosted game.
+    fn public_addresses(&self, bind_addr: SocketAddr) -> Result<Vec<SocketAddr>, Box<dyn Error>> {
+        if self.public_addrs.len() > MAX_PUBLIC_ADDRESSES {
+            return Err(
+                format!("at most {MAX_PUBLIC_ADDRESSES} public addresses are supported").into(),
+            );
+        }
+        if !self.public_addrs.is_empty() {
+            return Ok(self.public_addrs.clone());
+        }
+        if bind_addr.ip().is_unspecified() {
+            return Err(format!(
+                "--public-addr is required when hosting on the unspecified address {}",
+                bind_addr.ip()
+            )
+            .into());
+        }
+
+        Ok(vec![bind_addr])
+    }
+}
+
+/// Options of the start screen, shown until the player hosts or joins a game.
+#[derive(Resource)]
+struct StartScreen {
+    args: ClientArgs,
+    name: String,
+    port: String,
+    error: Option<String>,
+}
+
+impl StartScreen {
+    fn new(args: ClientArgs) -> Self {
+        Self {
+            name: args.name.clone().unwrap_or_default(),
+            port: args.port.to_string(),
+            args,
+            error: None,
+        }
+    }
+}
+
+fn main() {
+    let args = ClientArgs::parse();
+
+    let mut app = App::new();
+    app.add_plugins((
+        DefaultPlugins,
+        // A hosted game increments the replicon tick itself, see `bevy_ping_pong::tick`.
+        ReplicationPlugins.build().set(ServerPlugin {
+            tick_policy: TickPolicy::Manual,
+            visibility_policy: VisibilityPolicy::Whitelist,
+            ..Default::default()
+        }),
+    ))
+    .add_plugins((PingPongSharedPlugin, PingPongClientPlugin))
+    .add_plugins(EguiPlugin)
+    .insert_resource(InterpolationSettings {
+        delay: args.interpolation_delay as f32 / 1000.0,
         ..Default::default()
     });
 
+    if let Some(path) = &args.replay {
+        let replay = Replay::load(path)
+            .map_err(|e| format!("unable to read replay {}: {e}", path.display()))
+            .unwrap_or_else(|e| exit_with_error(e.into()));
+        app.add_plugins(ReplayPlaybackPlugin { replay });
+    } else if args.offline {
+        app.add_plugins((PingPongServerPlugin, HotSeatPlugin));
+    } else {
+        // The server only runs once a game is hosted, see `PingPongServerPlugin`.
+        app.add_plugins(PingPongServerPlugin);
+        if args.host {
+            host(&mut app.world, &args).unwrap_or_else(|e| exit_with_error(e));
+        } else if args.token.is_some() || args.unsecure {
+            join(&mut app.world, &args).unwrap_or_else(|e| exit_with_error(e));
+        } else {
+            app.insert_resource(StartScreen::new(args)).add_systems(
+                Update,
+                start_screen_system
+                    .run_if(resource_exists::<StartScreen>())
+                    .run_if(any_with_component::<EguiContext>()),
+            );
+        }
+    }
+
+    app.run();
+}
+
+fn exit_with_error(e: Box<dyn Error>) -> ! {
+    eprintln!("error: {e}");
+    process::exit(1);
+}
+
+fn create_transport(args: &ClientArgs) -> Result<NetcodeClientTransport, Box<dyn Error>> {
     let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
-    let client_id = current_time.as_millis() as u64;
-
-    let server_addr = SocketAddr::new(ip, PORT);
-    let socket = UdpSocket::bind((ip, 0))?;
-    let authentication = ClientAuthentication::Unsecure {
-        client_id,
-        protocol_id: PROTOCOL_ID,
-        server_addr,
-        user_data: None,
+    let (authentication, server_addr) = match &args.token {
+        Some(path) => {
+            let connect_token = read_connect_token(path)
+                .map_err(|e| format!("unable to read token {}: {e}", path.display()))?;
+            let server_addr =
+                connect_token.server_addresses[0].ok_or("token contains no server address")?;
+            println!("connecting to {server_addr} as {}", connect_token.client_id);
+            (ClientAuthentication::Secure { connect_token }, server_addr)
+        }
+        None => {
+            // Id 0 is reserved for the server itself, the highest ids for bots.
+            let client_id = args
+                .client_id
+                .unwrap_or_else(|| rand::thread_rng().gen_range(1..FIRST_BOT_ID));
+            let name = args
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("Player {client_id}"));
+            let server_addr = args.server_addr()?;
+            println!("connecting to {server_addr} as {name} ({client_id})");
+            let authentication = ClientAuthentication::Unsecure {
+                client_id,
+                protocol_id: args.protocol_id,
+                server_addr,
+                user_data: Some(name_to_user_data(&name)),
+            };
+            (authentication, server_addr)
+        }
+    };
+    let local_ip = match server_addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
     };
+    let socket = UdpSocket::bind((local_ip, 0))?;
     let transport = NetcodeClientTransport::new(current_time, authentication, socket)?;
 
-    commands.insert_resource(client);
-    commands.insert_resource(transport);
-
-    // commands.spawn(TextBundle::from_section(
-    //     format!("Client: {client_id:?}"),
-    //     TextStyle {
-    //         font_size: 30.0,
-    //         color: Color::WHITE,
-    //         ..default()
-    //     },
-    // ));
-    let x = LocalData {
-        client_id: client_id
+    Ok(transport)
+}
+
+/// Listens for the other players of a hosted game, who connect without a token.
+fn create_host_transport(args: &ClientArgs) -> Result<NetcodeServerTransport, Box<dyn Error>> {
+    let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let bind_addr = args.server_addr()?;
+    let public_addresses = args.public_addresses(bind_addr)?;
+    let socket =
+        UdpSocket::bind(bind_addr).map_err(|e| format!("unable to bind {bind_addr}: {e}"))?;
+    println!("hosting on {bind_addr}");
+    let server_config = ServerConfig {
+        current_time,
+        max_clients: HOST_MAX_CLIENTS,
+        protocol_id: args.protocol_id,
+        authentication: ServerAuthentication::Unsecure,
+        public_addresses,
     };
+    let transport = NetcodeServerTransport::new(server_config, socket)
+        .map_err(|e| format!("unable to start netcode transport: {e}"))?;
 
-    // commands.spawn(MaterialMesh2dBundle {
-    //     mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-    //     transform: Transform::default().with_scale(Vec3::splat(128.)),
-    //     material: materials.add(ColorMaterial::from(Color::PURPLE)),
-    //     ..default()
-    // });
-    commands.add(|world: &mut World| {
-        world.insert_resource(x)
+    Ok(transport)
+}
+
+fn connection_config(network_channels: &NetworkChannels) -> ConnectionConfig {
+    ConnectionConfig {
+        server_channels_config: network_channels.get_server_configs(),
+        client_channels_config: network_channels.get_client_configs(),
+        ..Default::default()
+    }
+}
+
+/// Connects to a server, the client plugin follows once the resources are inserted.
+fn join(world: &mut World, args: &ClientArgs) -> Result<(), Box<dyn Error>> {
+    let transport = create_transport(args)?;
+    let client = RenetClient::new(connection_config(world.resource::<NetworkChannels>()));
+    let client_id = transport.client_id();
+    world.insert_resource(transport);
+    world.insert_resource(client);
+    world.insert_resource(LocalData { client_id });
+
+    Ok(())
+}
+
+/// Hosts a game, the server plugin starts with the inserted rooms.
+fn host(world: &mut World, args: &ClientArgs) -> Result<(), Box<dyn Error>> {
+    let transport = create_host_transport(args)?;
+    let server = RenetServer::new(connection_config(world.resource::<NetworkChannels>()));
+    let name = args.name.clone().unwrap_or_else(|| "Host".to_string());
+    world.insert_resource(transport);
+    world.insert_resource(server);
+    world.insert_resource(HostPlayer { name });
+    world.insert_resource(LocalData {
+        client_id: SERVER_ID.raw(),
     });
+    world.insert_resource(Rooms::default());
+
     Ok(())
 }
+
+/// Lets the player host a game or join one when no mode was given on the command line.
+fn start_screen_system(
+    mut commands: Commands,
+    mut egui_ctx: Query<&mut EguiContext>,
+    mut start_screen: ResMut<StartScreen>,
+) {
+    let start_screen = &mut *start_screen;
+    let mut hosting = None;
+    egui::Window::new("Ping Pong")
+        .anchor(Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx.single_mut().get_mut(), |ui| {
+            egui::Grid::new("start_options")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut start_screen.name);
+                    ui.end_row();
+                    ui.label("Server");
+                    ui.text_edit_singleline(&mut start_screen.args.server);
+                    ui.end_row();
+                    ui.label("Port");
+                    ui.text_edit_singleline(&mut start_screen.port);
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                if ui.button("Host game").clicked() {
+                    hosting = Some(true);
+                }
+                if ui.button("Join game").clicked() {
+                    hosting = Some(false);
+                }
+            });
+            if let Some(error) = &start_screen.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+
+    let Some(hosting) = hosting else {
+        return;
+    };
+    let mut args = start_screen.args.clone();
+    let name = start_screen.name.trim();
+    args.name = (!name.is_empty()).then(|| name.to_string());
+    match start_screen.port.trim().parse() {
+        Ok(port) => args.port = port,
+        Err(e) => {
+            start_screen.error = Some(format!("invalid port: {e}"));
+            return;
+        }
+    }
+
+    commands.add(move |world: &mut World| {
+        let result = if hosting {
+            host(world, &args)
+        } else {
+            join(world, &args)
+        };
+        match result {
+            Ok(()) => {
+                world.remove_resource::<StartScreen>();
+            }
+            Err(e) => world.resource_mut::<StartScreen>().error = Some(e.to_string()),
+        }
+    });
+}
@@ -1,65 +1,379 @@
 use std::{
     error::Error,
-    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    process,
     time::SystemTime,
 };
 
-use bevy::{
-    a11y::AccessibilityPlugin,
-    app::ScheduleRunnerPlugin,
-    core_pipeline::CorePipelinePlugin,
-    diagnostic::DiagnosticsPlugin,
-    input::InputPlugin,
-    prelude::*,
-    render::{settings::WgpuSettings, RenderPlugin},
-    scene::ScenePlugin,
-    time::TimePlugin,
-    utils::Duration,
-    winit::WinitPlugin,
+use bevy::prelude::*;
+use bevy_ping_pong::{
+    auth::{parse_private_key, PrivateKey},
+    bot::{BotDifficulty, BotSettings},
+    replay::{create_replay_dir, ReplayRecorder},
+    room::Rooms,
+    rules::{MatchRules, WinCondition},
+    tick::TickRate,
+    validation::ValidationSettings,
+    PingPongServerPlugin, PingPongSharedPlugin, PORT, PROTOCOL_ID,
 };
-use bevy_ping_pong::{PingPongPlugin, PlayerBundle, PORT, PROTOCOL_ID};
 use bevy_replicon::replicon_core::NetworkChannels;
 use bevy_replicon::{
     prelude::*,
     renet::{
-        transport::{
-            ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport,
-            ServerAuthentication, ServerConfig,
-        },
-        ClientId, ConnectionConfig, ServerEvent,
+        transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
+        ConnectionConfig,
     },
 };
+use clap::{error::ErrorKind, ArgGroup, Args, CommandFactory, Parser};
+
+/// Netcode refuses more than this many public addresses.
+const MAX_PUBLIC_ADDRESSES: usize = 32;
+
+/// Headless ping pong server.
+#[derive(Debug, Parser)]
+#[command(name = "server", about)]
+#[command(group(ArgGroup::new("authentication").required(true)))]
+struct ServerArgs {
+    /// Local address the UDP socket is bound to.
+    #[arg(long, env = "PING_PONG_BIND", default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    bind: IpAddr,
+
+    /// UDP port the server listens on.
+    #[arg(short, long, env = "PING_PONG_PORT", default_value_t = PORT)]
+    port: u16,
+
+    /// Address clients connect to, may be repeated or comma separated.
+    /// Defaults to the bind address and port.
+    #[arg(
+        long = "public-addr",
+        env = "PING_PONG_PUBLIC_ADDRS",
+        value_delimiter = ','
+    )]
+    public_addrs: Vec<SocketAddr>,
+
+    /// Netcode protocol id, clients must use the same value.
+    #[arg(long, env = "PING_PONG_PROTOCOL_ID", default_value_t = PROTOCOL_ID)]
+    protocol_id: u64,
+
+    /// Maximum number of simultaneously connected clients, players and spectators together.
+    #[arg(
+        long,
+        env = "PING_PONG_MAX_CLIENTS",
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u16).range(1..=1024)
+    )]
+    max_clients: u16,
+
+    /// Maximum number of matches played at once, paired players wait in the lobby until a room
+    /// is free.
+    #[arg(
+        long,
+        env = "PING_PONG_MAX_ROOMS",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..=512)
+    )]
+    max_rooms: u16,
+
+    /// Simulation ticks per second, clients take over the rate when they connect.
+    #[arg(
+        long,
+        env = "PING_PONG_TICK_RATE",
+        default_value_t = 60,
+        value_parser = clap::value_parser!(u16).range(1..=240)
+    )]
+    tick_rate: u16,
+
+    /// Seed of the random serves, a random seed is used when omitted.
+    #[arg(long, env = "PING_PONG_SEED")]
+    seed: Option<u64>,
+
+    /// Pair players who wait alone for a match with a bot of this difficulty: easy, medium or
+    /// hard. Players can always start a match against a bot from the lobby.
+    #[arg(long, env = "PING_PONG_BOT")]
+    bot: Option<BotDifficulty>,
+
+    /// Seconds a player waits alone before a bot joins, see --bot.
+    #[arg(
+        long,
+        env = "PING_PONG_BOT_WAIT",
+        default_value_t = 5.0,
+        value_parser = parse_wait_secs
+    )]
+    bot_wait: f32,
+
+    /// Input violations a client may commit before it's disconnected, such as invalid
+    /// directions or more inputs than ticks. Violations are forgiven over time.
+    #[arg(long, env = "PING_PONG_MAX_VIOLATIONS", default_value_t = 20)]
+    max_violations: u32,
+
+    /// Directory every match is recorded into, matches aren't recorded when omitted. The
+    /// client plays the recordings back with --replay.
+    #[arg(long, env = "PING_PONG_REPLAY_DIR")]
+    replay_dir: Option<PathBuf>,
+
+    /// Private key used to validate connect tokens, as hex.
+    #[arg(
+        long,
+        env = "PING_PONG_PRIVATE_KEY",
+        group = "authentication",
+        value_parser = parse_private_key,
+        hide_env_values = true
+    )]
+    private_key: Option<PrivateKey>,
+
+    /// Accept unauthenticated clients, for local development only.
+    #[arg(long, group = "authentication")]
+    unsecure: bool,
+
+    #[command(flatten)]
+    rules: RulesArgs,
+}
+
+/// Parses a finite, non-negative number of seconds.
+fn parse_wait_secs(arg: &str) -> Result<f32, String> {
+    let secs: f32 = arg.parse().map_err(|e| format!("{e}"))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!(
+            "expected a non-negative number of seconds, got {arg}"
+        ));
+    }
+
+    Ok(secs)
+}
+
+/// Match rules, options override the values loaded from `--rules`.
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Match rules")]
+struct RulesArgs {
+    /// RON file with the match rules.
+    #[arg(long = "rules", env = "PING_PONG_RULES")]
+    path: Option<PathBuf>,
+
+    /// Play a fixed number of rounds.
+    #[arg(long, conflicts_with = "target_score")]
+    rounds: Option<u16>,
+
+    /// Play until a player reaches this score.
+    #[arg(long)]
+    target_score: Option<u16>,
+
+    /// Require a two point lead to win: true or false.
+    #[arg(long)]
+    win_by_two: Option<bool>,
+
+    /// Ball speed at the start of every round, in units per second.
+    #[arg(long)]
+    ball_speed: Option<f32>,
+
+    /// Fraction of its speed the ball gains on every paddle hit.
+    #[arg(long)]
+    speed_up: Option<f32>,
+
+    /// The ball doesn't speed up beyond this, in units per second.
+    #[arg(long)]
+    max_ball_speed: Option<f32>,
+
+    /// Angle of the ball leaving a paddle hit at its edge, in degrees.
+    #[arg(long)]
+    max_bounce_angle: Option<f32>,
+
+    /// Fraction of the vertical paddle velocity passed on to the ball.
+    #[arg(long)]
+    paddle_spin: Option<f32>,
+
+    /// Largest angle of a serve, in degrees.
+    #[arg(long)]
+    max_serve_angle: Option<f32>,
+
+    /// Seconds the ball waits in the center before every serve.
+    #[arg(long)]
+    serve_delay: Option<f32>,
+
+    /// Paddle speed, in units per second.
+    #[arg(long)]
+    paddle_speed: Option<f32>,
+
+    #[arg(long)]
+    paddle_width: Option<f32>,
+
+    #[arg(long)]
+    paddle_height: Option<f32>,
+
+    /// Side length of the square ball.
+    #[arg(long)]
+    ball_size: Option<f32>,
+
+    #[arg(long)]
+    field_width: Option<f32>,
+
+    #[arg(long)]
+    field_height: Option<f32>,
+
+    /// Seconds a disconnected player has to rejoin before forfeiting the match.
+    #[arg(long)]
+    reconnect_grace: Option<f32>,
+}
+
+impl RulesArgs {
+    fn load(&self) -> Result<MatchRules, Box<dyn Error>> {
+        let mut rules = match &self.path {
+            Some(path) => MatchRules::load(path)
+                .map_err(|e| format!("unable to load rules from {}: {e}", path.display()))?,
+            None => MatchRules::default(),
+        };
+
+        if let Some(rounds) = self.rounds {
+            rules.win_condition = WinCondition::Rounds(rounds);
+        }
+        if let Some(target_score) = self.target_score {
+            rules.win_condition = WinCondition::TargetScore(target_score);
+        }
+        if let Some(win_by_two) = self.win_by_two {
+            rules.win_by_two = win_by_two;
+        }
+        let overrides = [
+            (self.ball_speed, &mut rules.ball_speed),
+            (self.speed_up, &mut rules.speed_up_per_hit),
+            (self.max_ball_speed, &mut rules.max_ball_speed),
+            (self.max_bounce_angle, &mut rules.max_bounce_angle),
+            (self.paddle_spin, &mut rules.paddle_spin),
+            (self.max_serve_angle, &mut rules.max_serve_angle),
+            (self.serve_delay, &mut rules.serve_delay_secs),
+            (self.paddle_speed, &mut rules.paddle_speed),
+            (self.paddle_width, &mut rules.paddle_size.x),
+            (self.paddle_height, &mut rules.paddle_size.y),
+            (self.ball_size, &mut rules.ball_size),
+            (self.field_width, &mut rules.field_size.x),
+            (self.field_height, &mut rules.field_size.y),
+            (self.reconnect_grace, &mut rules.reconnect_grace_secs),
+        ];
+        for (value, field) in overrides {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        rules
+            .validate()
+            .map_err(|e| format!("invalid match rules: {e}"))?;
+
+        Ok(rules)
+    }
+}
+
+impl ServerArgs {
+    fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    /// Returns the addresses advertised to clients, exiting with a usage error if they are invalid.
+    fn public_addresses(&self) -> Vec<SocketAddr> {
+        if self.public_addrs.len() > MAX_PUBLIC_ADDRESSES {
+            ServerArgs::command()
+                .error(
+                    ErrorKind::TooManyValues,
+                    format!("at most {MAX_PUBLIC_ADDRESSES} public addresses are supported"),
+                )
+                .exit();
+        }
+        if !self.public_addrs.is_empty() {
+            return self.public_addrs.clone();
+        }
+        if self.bind.is_unspecified() {
+            ServerArgs::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    format!(
+                        "--public-addr is required when binding to the unspecified address {}",
+                        self.bind
+                    ),
+                )
+                .exit();
+        }
+        vec![self.bind_addr()]
+    }
+
+    fn authentication(&self) -> ServerAuthentication {
+        match self.private_key {
+            Some(private_key) => ServerAuthentication::Secure { private_key },
+            None => ServerAuthentication::Unsecure,
+        }
+    }
+}
 
 fn main() {
-    App::new()
+    let args = ServerArgs::parse();
+    let rules = args.rules.load().unwrap_or_else(|e| exit_with_error(e));
+    let transport = create_transport(&args).unwrap_or_else(|e| exit_with_error(e));
+    println!("listening on {}", args.bind_addr());
+    if args.unsecure {
+        println!("warning: accepting unauthenticated clients");
+    }
+
+    let mut rooms = Rooms::new(args.max_rooms.into());
+    if let Some(seed) = args.seed {
+        rooms.seed = seed;
+    }
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
         .add_plugins((
-            // //DefaultPlugins
-            // DefaultPlugins.set(Womd,RenderPlugin {
-            //     render_creation: WgpuSettings {
-            //         backends: None,
-            //         ..default()
-            //     }
-            //     .into(),
-            // }).set(TimePlugin {
-
-            // }),
-            MinimalPlugins,
-            // WindowPlugin {
-            //     primary_window: None,
-            //     exit_condition: bevy::window::ExitCondition::DontExit,
-            //     ..Default::default()
-            // }
+            // The simulation increments the replicon tick, see `bevy_ping_pong::tick`.
+            ReplicationPlugins.build().set(ServerPlugin {
+                tick_policy: TickPolicy::Manual,
+                visibility_policy: VisibilityPolicy::Whitelist,
+                ..Default::default()
+            }),
+            PingPongSharedPlugin,
+            PingPongServerPlugin,
         ))
-        .add_plugins((ReplicationPlugins, PingPongPlugin))
-        .add_systems(Startup, init_server.map(Result::unwrap))
-        .add_systems(Startup, bevy_ping_pong::PingPongPlugin::init_system_server)
-        .run();
+        .insert_resource(transport)
+        .insert_resource(rules)
+        .insert_resource(rooms)
+        .insert_resource(TickRate(args.tick_rate))
+        .insert_resource(BotSettings {
+            fill: args.bot,
+            fill_wait_secs: args.bot_wait,
+        })
+        .insert_resource(ValidationSettings {
+            max_violations: args.max_violations,
+        })
+        .add_systems(Startup, init_server);
+
+    if let Some(dir) = args.replay_dir {
+        create_replay_dir(&dir).unwrap_or_else(|e| exit_with_error(e));
+        println!("recording matches into {}", dir.display());
+        app.insert_resource(ReplayRecorder::new(dir));
+    }
+
+    app.run();
+}
+
+fn exit_with_error(e: Box<dyn Error>) -> ! {
+    eprintln!("error: {e}");
+    process::exit(1);
+}
+
+fn create_transport(args: &ServerArgs) -> Result<NetcodeServerTransport, Box<dyn Error>> {
+    let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let bind_addr = args.bind_addr();
+    let socket =
+        UdpSocket::bind(bind_addr).map_err(|e| format!("unable to bind {bind_addr}: {e}"))?;
+    let server_config = ServerConfig {
+        current_time,
+        max_clients: args.max_clients.into(),
+        protocol_id: args.protocol_id,
+        authentication: args.authentication(),
+        public_addresses: args.public_addresses(),
+    };
+    let transport = NetcodeServerTransport::new(server_config, socket)
+        .map_err(|e| format!("unable to start netcode transport: {e}"))?;
+
+    Ok(transport)
 }
 
-fn init_server(
-    mut commands: Commands,
-    network_channels: Res<NetworkChannels>,
-) -> Result<(), Box<dyn Error>> {
+fn init_server(mut commands: Commands, network_channels: Res<NetworkChannels>) {
     let server_channels_config = network_channels.get_server_configs();
     let client_channels_config = network_channels.get_client_configs();
 
@@ -69,30 +383,5 @@ fn init_server(
         ..Default::default()
     });
 
-    let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
-    let public_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
-    let socket = UdpSocket::bind(public_addr)?;
-    let server_config = ServerConfig {
-        current_time,
-        max_clients: 2,
-        protocol_id: PROTOCOL_ID,
-        authentication: ServerAuthentication::Unsecure,
-        public_addresses: vec![public_addr],
-    };
-    let transport = NetcodeServerTransport::new(server_config, socket)?;
-
     commands.insert_resource(server);
-    commands.insert_resource(transport);
-
-    commands.spawn(TextBundle::from_section(
-        "Server",
-        TextStyle {
-            font_size: 30.0,
-            color: Color::WHITE,
-            ..default()
-        },
-    ));
-    //commands.spawn(PlayerBundle::new(SERVER_ID, Vec2::ZERO, Color::GREEN));
-    println!("init system");
-    Ok(())
 }
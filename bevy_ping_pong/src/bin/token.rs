@@ -0,0 +1,86 @@
+use std::{net::SocketAddr, path::PathBuf, process};
+
+use bevy_ping_pong::{
+    auth::{
+        generate_private_key, issue_connect_token, parse_private_key, private_key_to_hex,
+        write_connect_token, PrivateKey,
+    },
+    bot::FIRST_BOT_ID,
+    PORT, PROTOCOL_ID,
+};
+use clap::{Parser, Subcommand};
+
+/// Issues connect tokens for servers running in secure mode.
+#[derive(Debug, Parser)]
+#[command(name = "token", about)]
+struct TokenArgs {
+    #[command(subcommand)]
+    command: TokenCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum TokenCommand {
+    /// Print a new random private key to share between the server and the issuer.
+    Key,
+    /// Issue a connect token for one client.
+    Issue {
+        /// Private key shared with the server, as hex.
+        #[arg(
+            long,
+            env = "PING_PONG_PRIVATE_KEY",
+            value_parser = parse_private_key,
+            hide_env_values = true
+        )]
+        private_key: PrivateKey,
+
+        /// Netcode protocol id, must match the server.
+        #[arg(long, env = "PING_PONG_PROTOCOL_ID", default_value_t = PROTOCOL_ID)]
+        protocol_id: u64,
+
+        /// Address the client will connect to, may be repeated or comma separated.
+        #[arg(
+            long = "server-addr",
+            env = "PING_PONG_PUBLIC_ADDRS",
+            value_delimiter = ',',
+            default_values_t = [SocketAddr::from(([127, 0, 0, 1], PORT))]
+        )]
+        server_addrs: Vec<SocketAddr>,
+
+        /// Client id the token is issued for.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..FIRST_BOT_ID))]
+        client_id: u64,
+
+        /// Name shown to other players.
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// File the token is written to.
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+}
+
+fn main() {
+    match TokenArgs::parse().command {
+        TokenCommand::Key => println!("{}", private_key_to_hex(&generate_private_key())),
+        TokenCommand::Issue {
+            private_key,
+            protocol_id,
+            server_addrs,
+            client_id,
+            name,
+            out,
+        } => {
+            let name = name.unwrap_or_else(|| format!("Player {client_id}"));
+            let result =
+                issue_connect_token(&private_key, protocol_id, client_id, &name, server_addrs)
+                    .map_err(Into::into)
+                    .and_then(|token| write_connect_token(&token, &out));
+            if let Err(e) = result {
+                eprintln!("error: unable to issue token: {e}");
+                process::exit(1);
+            }
+            println!("issued token for {name} ({client_id}) to {}", out.display());
+        }
+    }
+}
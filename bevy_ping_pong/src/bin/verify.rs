@@ -0,0 +1,99 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use bevy_ping_pong::{
+    replay::{Replay, REPLAY_EXTENSION},
+    verify::verify,
+};
+use clap::Parser;
+
+/// Re-simulates recorded matches and checks that they play out exactly like the recording.
+///
+/// Every replay is simulated from its recorded inputs and seed. Exits with status 1 if any of
+/// them diverges, which means the simulation changed or isn't deterministic.
+#[derive(Debug, Parser)]
+#[command(name = "verify", about)]
+struct VerifyArgs {
+    /// Replay files, or directories whose replays are all verified.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+fn main() {
+    let args = VerifyArgs::parse();
+    let files = replay_files(&args.paths).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        process::exit(1);
+    });
+    if files.is_empty() {
+        eprintln!("error: no replays found");
+        process::exit(1);
+    }
+
+    let mut failed = 0;
+    for path in &files {
+        match verify_file(path) {
+            Ok(summary) => println!("ok      {}: {summary}", path.display()),
+            Err(e) => {
+                failed += 1;
+                println!("FAILED  {}: {e}", path.display());
+            }
+        }
+    }
+
+    println!(
+        "{} of {} replays verified",
+        files.len() - failed,
+        files.len()
+    );
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// Collects the given files and the replays in the given directories, in a stable order.
+fn replay_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let entries = fs::read_dir(path)
+            .map_err(|e| format!("unable to read directory {}: {e}", path.display()))?;
+        let mut replays = Vec::new();
+        for entry in entries {
+            let file = entry?.path();
+            if file
+                .extension()
+                .is_some_and(|extension| extension == REPLAY_EXTENSION)
+            {
+                replays.push(file);
+            }
+        }
+        replays.sort();
+        files.extend(replays);
+    }
+
+    Ok(files)
+}
+
+fn verify_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let replay = Replay::load(path)?;
+    let ticks = verify(&replay)?;
+    let score = replay
+        .frames
+        .last()
+        .map(|frame| frame.score)
+        .unwrap_or_default();
+    let [left, right] = &replay.players;
+
+    Ok(format!(
+        "{ticks} ticks, {} {}:{} {}",
+        left.name, score.left, score.right, right.name
+    ))
+}
@@ -0,0 +1,456 @@
+//! Computer opponents simulated on the server.
+//!
+//! A bot takes a paddle like a connected player, but it never connects: it gets a client id from
+//! the range starting at [`FIRST_BOT_ID`], which clients can't use, and its inputs are queued for
+//! the simulation directly. Players start a match against a bot from the lobby. With a fill
+//! difficulty in the [`BotSettings`], a player who waits alone in the matchmaking queue is paired
+//! with a bot as well.
+//!
+//! The bot predicts where the ball crosses its paddle, bouncing it off the walls. How well it
+//! plays depends on its [`BotDifficulty`]: it only looks at the ball again after its reaction
+//! time, aims next to the predicted point and moves slower than a player can. After the match it
+//! accepts every rematch and leaves together with its opponent.
+
+use std::{fmt, str::FromStr};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::{prelude::*, renet::ClientId};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lobby::{send_to_lobby, Lobby},
+    physics::predict_crossing,
+    room::{send_to_room, RoomId, RoomMessage, Rooms},
+    rules::MatchRules,
+    send_to_clients,
+    tick::TickRate,
+    Ball, C2sMessage, ClientMessage, GameState, InputQueues, MoveDirection, Paddle,
+    PingPongServerPlugin, Player, PlayerPosition, PlayerSpeed, S2cMessage, ServerMessage,
+};
+
+/// Client ids from here on are reserved for bots.
+pub const FIRST_BOT_ID: u64 = 1 << 63;
+
+/// Returns whether the client id belongs to a bot.
+pub fn is_bot(client_id: u64) -> bool {
+    client_id >= FIRST_BOT_ID
+}
+
+/// Inputs moving the paddle less than this fraction of its speed aren't sent.
+const MIN_INPUT: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum BotDifficulty {
+    Easy,
+    #[default]
+    Medium,
+    Hard,
+}
+
+impl BotDifficulty {
+    pub const ALL: [Self; 3] = [Self::Easy, Self::Medium, Self::Hard];
+
+    /// Seconds until the bot notices where the ball is going.
+    pub fn reaction_secs(self) -> f32 {
+        match self {
+            Self::Easy => 0.4,
+            Self::Medium => 0.2,
+            Self::Hard => 0.05,
+        }
+    }
+
+    /// Fastest paddle movement as a fraction of the paddle speed.
+    pub fn max_speed(self) -> f32 {
+        match self {
+            Self::Easy => 0.6,
+            Self::Medium => 0.8,
+            Self::Hard => 1.0,
+        }
+    }
+
+    /// Largest distance between the point the bot aims at and the predicted one.
+    pub fn prediction_error(self) -> f32 {
+        match self {
+            Self::Easy => 60.0,
+            Self::Medium => 25.0,
+            Self::Hard => 5.0,
+        }
+    }
+}
+
+impl fmt::Display for BotDifficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Easy => "easy",
+            Self::Medium => "medium",
+            Self::Hard => "hard",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for BotDifficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.to_string() == s)
+            .ok_or_else(|| format!("unknown difficulty {s}, expected easy, medium or hard"))
+    }
+}
+
+#[derive(Resource)]
+pub struct BotSettings {
+    /// Difficulty of the bots pairing with players who wait alone, `None` to only play bots on
+    /// request.
+    pub fill: Option<BotDifficulty>,
+    /// Seconds a player waits alone in the queue before a bot joins.
+    pub fill_wait_secs: f32,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            fill: None,
+            fill_wait_secs: 5.0,
+        }
+    }
+}
+
+/// Moves a paddle towards the point where the ball will cross it, as well as the difficulty
+/// allows.
+///
+/// Steers the bots on the server and the [`AutoPilotPlugin`](crate::autopilot::AutoPilotPlugin)
+/// of headless clients.
+pub(crate) struct Steering {
+    difficulty: BotDifficulty,
+    rng: StdRng,
+    /// Height the paddle moves to.
+    target_y: f32,
+    /// Tick at which the ball is looked at again.
+    next_decision: u32,
+}
+
+impl Steering {
+    pub(crate) fn new(difficulty: BotDifficulty, seed: u64) -> Self {
+        Self {
+            difficulty,
+            rng: StdRng::seed_from_u64(seed),
+            target_y: 0.0,
+            next_decision: 0,
+        }
+    }
+
+    /// Returns the vertical input of the paddle for a tick, `None` if it's close enough to its
+    /// target.
+    pub(crate) fn input(
+        &mut self,
+        tick: u32,
+        rules: &MatchRules,
+        tick_rate: TickRate,
+        paddle: Vec2,
+        ball: Vec2,
+        ball_velocity: Vec2,
+    ) -> Option<f32> {
+        if tick >= self.next_decision {
+            // The paddle waits in the center while the ball moves away from it.
+            let predicted =
+                predict_crossing(ball, ball_velocity, paddle.x, rules.ball_max().y).unwrap_or(0.0);
+            let error = self.difficulty.prediction_error();
+            self.target_y = predicted + self.rng.gen_range(-error..=error);
+            let reaction_ticks = (self.difficulty.reaction_secs() * tick_rate.0 as f32).round();
+            self.next_decision = tick + (reaction_ticks as u32).max(1);
+        }
+
+        let max_speed = self.difficulty.max_speed();
+        let distance = (self.target_y - paddle.y) / (rules.paddle_speed * tick_rate.duration());
+        let input = distance.clamp(-max_speed, max_speed);
+        (input.abs() >= MIN_INPUT).then_some(input)
+    }
+}
+
+struct Bot {
+    steering: Steering,
+    sequence: u32,
+}
+
+/// The bots on the server, keyed by client id.
+#[derive(Resource)]
+pub(crate) struct Bots {
+    next_id: u64,
+    bots: HashMap<u64, Bot>,
+}
+
+impl Default for Bots {
+    fn default() -> Self {
+        Self {
+            next_id: FIRST_BOT_ID,
+            bots: HashMap::default(),
+        }
+    }
+}
+
+impl Bots {
+    /// Creates a bot and puts it into the lobby, returns its client id.
+    ///
+    /// The moves of the bot are derived from `seed`, like the serves of a room.
+    pub(crate) fn spawn(
+        &mut self,
+        lobby: &mut Lobby,
+        game_message_events: &mut EventWriter<ToClients<ServerMessage>>,
+        difficulty: BotDifficulty,
+        seed: u64,
+    ) -> u64 {
+        let client_id = self.next_id;
+        self.next_id += 1;
+        self.bots.insert(
+            client_id,
+            Bot {
+                steering: Steering::new(difficulty, seed ^ client_id),
+                sequence: 0,
+            },
+        );
+        info!("spawned {difficulty} bot {client_id}");
+        PingPongServerPlugin::enter_lobby(
+            lobby,
+            game_message_events,
+            client_id,
+            format!("Bot ({difficulty})"),
+        );
+
+        client_id
+    }
+}
+
+/// Pairs a player who waits alone in the queue with a bot once the fill wait is over.
+pub(crate) fn fill_queue_system(
+    time: Res<Time>,
+    settings: Res<BotSettings>,
+    rooms: Res<Rooms>,
+    mut lobby: ResMut<Lobby>,
+    mut bots: ResMut<Bots>,
+    mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+    mut waited: Local<f32>,
+) {
+    let Some(difficulty) = settings.fill else {
+        return;
+    };
+    let queued: Vec<_> = lobby.queued().collect();
+    let [client_id] = queued[..] else {
+        *waited = 0.0;
+        return;
+    };
+    *waited += time.delta_seconds();
+    if *waited < settings.fill_wait_secs {
+        return;
+    }
+
+    *waited = 0.0;
+    let bot_id = bots.spawn(&mut lobby, &mut game_message_events, difficulty, rooms.seed);
+    info!("{client_id} waited alone, pairing with bot {bot_id}");
+    for (challenger, challenged) in lobby.pair(client_id, bot_id) {
+        send_to_clients(
+            &mut game_message_events,
+            [challenger, challenged],
+            S2cMessage::ChallengeDeclined(challenger, challenged),
+        );
+    }
+}
+
+/// Queues the inputs of every bot in a running match for the next tick.
+pub(crate) fn input_system(
+    rules: Res<MatchRules>,
+    tick_rate: Res<TickRate>,
+    replicon_tick: Res<RepliconTick>,
+    rooms: Res<Rooms>,
+    mut bots: ResMut<Bots>,
+    mut queues: ResMut<InputQueues>,
+    paddles: Query<(&Player, &RoomId, &PlayerPosition), With<Paddle>>,
+    balls: Query<(&RoomId, &PlayerPosition, &PlayerSpeed), With<Ball>>,
+) {
+    let tick = replicon_tick.get();
+    for (&client_id, bot) in bots.bots.iter_mut() {
+        let Some(room_id) = rooms.room_of(client_id) else {
+            continue;
+        };
+        if !rooms
+            .get(room_id)
+            .is_some_and(|room| room.game_data.phase == GameState::Game)
+        {
+            continue;
+        }
+        let Some((_, _, paddle)) = paddles.iter().find(|(player, paddle_room_id, _)| {
+            player.0.raw() == client_id && **paddle_room_id == room_id
+        }) else {
+            continue;
+        };
+        let Some((_, ball, velocity)) = balls
+            .iter()
+            .find(|(ball_room_id, ..)| **ball_room_id == room_id)
+        else {
+            continue;
+        };
+
+        let Some(input) = bot
+            .steering
+            .input(tick, &rules, *tick_rate, **paddle, **ball, **velocity)
+        else {
+            continue;
+        };
+        bot.sequence = bot.sequence.wrapping_add(1);
+        queues
+            .entry(ClientId::from_raw(client_id))
+            .or_default()
+            .push_back(MoveDirection {
+                direction: Vec2::new(0.0, input),
+                sequence: bot.sequence,
+            });
+    }
+}
+
+/// Lets bots answer rematch votes and leave finished matches or the lobby together with their
+/// opponents.
+pub(crate) fn room_system(
+    mut rooms: ResMut<Rooms>,
+    mut lobby: ResMut<Lobby>,
+    mut bots: ResMut<Bots>,
+    mut client_messages: EventWriter<FromClient<ClientMessage>>,
+    mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+    mut room_messages: EventWriter<RoomMessage>,
+) {
+    let mut removed = Vec::new();
+    for &client_id in bots.bots.keys() {
+        let Some(room_id) = rooms.room_of(client_id) else {
+            // Bots only wait in the lobby until their match starts, a bot whose opponent left
+            // before a room was free leaves as well.
+            if !lobby.is_paired(client_id) {
+                removed.push(client_id);
+            }
+            continue;
+        };
+        let game_data = &mut rooms
+            .get_mut(room_id)
+            .expect("client room should exist")
+            .game_data;
+        if game_data.phase != GameState::End {
+            continue;
+        }
+        let opponent_id = if client_id == game_data.actor1 {
+            game_data.actor2
+        } else {
+            game_data.actor1
+        };
+
+        if game_data.left.contains(&opponent_id) {
+            info!("bot {client_id} left room {}", room_id.0);
+            PingPongServerPlugin::free_slot(game_data, client_id);
+            send_to_room(
+                &mut game_message_events,
+                &mut room_messages,
+                room_id,
+                game_data,
+                S2cMessage::PlayerLeft(client_id),
+            );
+            rooms.leave(client_id);
+            removed.push(client_id);
+        } else if game_data.rematch_votes.contains(&opponent_id)
+            && !game_data.rematch_votes.contains(&client_id)
+        {
+            client_messages.send(FromClient {
+                client_id: ClientId::from_raw(client_id),
+                event: ClientMessage {
+                    msg: C2sMessage::Rematch,
+                },
+            });
+        }
+    }
+
+    for client_id in removed {
+        if lobby.contains(client_id) {
+            lobby.leave(client_id);
+            send_to_lobby(
+                &mut game_message_events,
+                &lobby,
+                S2cMessage::LobbyLeft(client_id),
+            );
+        }
+        bots.bots.remove(&client_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const CLIENT_ID: u64 = 1;
+
+    /// A server with a single ready player in the lobby and bots filling in after two seconds.
+    fn fill_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Rooms>()
+            .init_resource::<Lobby>()
+            .init_resource::<Bots>()
+            .insert_resource(BotSettings {
+                fill: Some(BotDifficulty::Easy),
+                fill_wait_secs: 2.0,
+            })
+            .add_event::<ToClients<ServerMessage>>()
+            .add_systems(Update, fill_queue_system);
+        let mut lobby = app.world.resource_mut::<Lobby>();
+        lobby.enter(CLIENT_ID, "Player".into());
+        lobby.set_ready(CLIENT_ID, true);
+
+        app
+    }
+
+    /// Runs the app once, one second after its last update.
+    fn update_after_a_second(app: &mut App) {
+        app.world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        app.update();
+    }
+
+    #[test]
+    fn bot_joins_after_fill_wait() {
+        let mut app = fill_app();
+
+        update_after_a_second(&mut app);
+        assert!(!app.world.resource::<Lobby>().is_paired(CLIENT_ID));
+        update_after_a_second(&mut app);
+        assert!(app.world.resource::<Lobby>().is_paired(CLIENT_ID));
+        assert_eq!(app.world.resource::<Bots>().bots.len(), 1);
+    }
+
+    #[test]
+    fn fill_wait_restarts_when_player_leaves_queue() {
+        let mut app = fill_app();
+
+        update_after_a_second(&mut app);
+        app.world
+            .resource_mut::<Lobby>()
+            .set_ready(CLIENT_ID, false);
+        update_after_a_second(&mut app);
+        app.world.resource_mut::<Lobby>().set_ready(CLIENT_ID, true);
+        update_after_a_second(&mut app);
+        assert!(!app.world.resource::<Lobby>().is_paired(CLIENT_ID));
+        update_after_a_second(&mut app);
+        assert!(app.world.resource::<Lobby>().is_paired(CLIENT_ID));
+    }
+
+    #[test]
+    fn no_bot_joins_without_fill_difficulty() {
+        let mut app = fill_app();
+        app.world.resource_mut::<BotSettings>().fill = None;
+
+        for _ in 0..10 {
+            update_after_a_second(&mut app);
+        }
+        assert!(!app.world.resource::<Lobby>().is_paired(CLIENT_ID));
+    }
+}
@@ -0,0 +1,132 @@
+//! Offline matches of two players sharing one keyboard.
+//!
+//! The [`HotSeatPlugin`] turns an app with the server and the client plugins into a hot-seat
+//! game that needs no renet resources. The local client plays as the [`HostPlayer`] with W and
+//! S, a guest player sits next to it in the lobby and moves its paddle with the arrow keys. Both
+//! players are simulated by the same `movement_system` as networked matches.
+//!
+//! The host is ready right away, and the guest follows its ready flag, rematch votes and leave
+//! requests, so both get paired, play again and go back to the lobby together. The host becomes
+//! ready first and therefore always plays the left paddle. With the [`HotSeat`] resource the lobby
+//! only offers to start the next match, there are no other players to challenge or watch and a
+//! bot would leave the guest without a paddle.
+
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use bevy_replicon::{prelude::*, renet::ClientId};
+
+use crate::{
+    lobby::Lobby, room::Rooms, C2sMessage, ClientMessage, GameState, HostPlayer, LocalData,
+    MoveDirection, PaddleKeys, PingPongServerPlugin, ServerMessage,
+};
+
+/// Client id of the guest player.
+pub const GUEST_ID: u64 = 1;
+
+/// Marks an app playing a hot-seat game.
+#[derive(Resource)]
+pub struct HotSeat;
+
+/// Plays a match of two local players, see the module docs.
+///
+/// Needs the [`PingPongSharedPlugin`](crate::PingPongSharedPlugin), the
+/// [`PingPongServerPlugin`] and the [`PingPongClientPlugin`](crate::PingPongClientPlugin).
+pub struct HotSeatPlugin;
+
+impl Plugin for HotSeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rooms>()
+            .insert_resource(HotSeat)
+            .insert_resource(HostPlayer {
+                name: "Player 1 (W/S)".to_string(),
+            })
+            .insert_resource(LocalData {
+                client_id: SERVER_ID.raw(),
+            })
+            .insert_resource(PaddleKeys {
+                up: KeyCode::W,
+                down: KeyCode::S,
+            })
+            .add_systems(Startup, (enter_guest_system, start_system))
+            .add_systems(
+                FixedUpdate,
+                guest_input_system.run_if(in_state(GameState::Game)),
+            )
+            .add_systems(
+                Update,
+                follow_host_system.before(PingPongServerPlugin::lobby_system),
+            );
+    }
+}
+
+fn enter_guest_system(
+    mut lobby: ResMut<Lobby>,
+    mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+) {
+    PingPongServerPlugin::enter_lobby(
+        &mut lobby,
+        &mut game_message_events,
+        GUEST_ID,
+        "Player 2 (Up/Down)".to_string(),
+    );
+}
+
+/// Readies the host, so the first match starts without going through the lobby.
+fn start_system(mut client_messages: EventWriter<ClientMessage>) {
+    client_messages.send(ClientMessage {
+        msg: C2sMessage::SetReady(true),
+    });
+}
+
+/// Samples the arrow keys for the guest once per simulation tick.
+///
+/// Like the keys of the host, they are only sampled during a match. The server would count
+/// inputs from the lobby or the end screen as violations.
+fn guest_input_system(
+    mut move_events: EventWriter<FromClient<MoveDirection>>,
+    input: Res<Input<KeyCode>>,
+    mut sequence: Local<u32>,
+) {
+    let mut direction = Vec2::ZERO;
+    if input.pressed(KeyCode::Up) {
+        direction.y += 1.0;
+    }
+    if input.pressed(KeyCode::Down) {
+        direction.y -= 1.0;
+    }
+    if direction == Vec2::ZERO {
+        return;
+    }
+
+    *sequence = sequence.wrapping_add(1);
+    move_events.send(FromClient {
+        client_id: ClientId::from_raw(GUEST_ID),
+        event: MoveDirection {
+            direction,
+            sequence: *sequence,
+        },
+    });
+}
+
+/// Repeats the ready flag, rematch votes and leave requests of the host for the guest.
+fn follow_host_system(
+    mut client_messages: ResMut<Events<FromClient<ClientMessage>>>,
+    mut reader: Local<ManualEventReader<FromClient<ClientMessage>>>,
+) {
+    let guest_messages: Vec<_> = reader
+        .read(&client_messages)
+        .filter(|message| message.client_id == SERVER_ID)
+        .filter_map(|message| match message.event.msg {
+            C2sMessage::SetReady(ready) => Some(C2sMessage::SetReady(ready)),
+            C2sMessage::Rematch => Some(C2sMessage::Rematch),
+            C2sMessage::Leave => Some(C2sMessage::Leave),
+            _ => None,
+        })
+        .collect();
+
+    for msg in guest_messages {
+        client_messages.send(FromClient {
+            client_id: ClientId::from_raw(GUEST_ID),
+            event: ClientMessage { msg },
+        });
+    }
+}
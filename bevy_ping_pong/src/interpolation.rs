@@ -0,0 +1,256 @@
+//! Snapshot interpolation of remote paddles and the ball.
+//!
+//! Whenever a server update for its room arrives, a client records the replicated
+//! [`PlayerPosition`](crate::PlayerPosition) of every entity in the room in a [`SnapshotBuffer`],
+//! stamped with the simulation time of the server tick it describes. Entities that didn't move
+//! get a snapshot as well, since replicon only sends changed components. The [`ServerClock`]
+//! estimates the current server time from the arriving ticks, and remote entities are drawn at
+//! the [`InterpolatedPosition`] between the two snapshots around a point slightly in the past, so
+//! unevenly arriving packets don't make them stutter. When no newer snapshot arrived in time the
+//! position is extrapolated from the last two snapshots for a limited time.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+/// Snapshots kept per entity at most.
+const MAX_SNAPSHOTS: usize = 64;
+
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct InterpolationSettings {
+    /// How far in the past remote entities are drawn, in seconds.
+    ///
+    /// Should cover a few server updates, so there's a newer snapshot to interpolate to.
+    pub delay: f32,
+    /// How long a position is extrapolated after the last snapshot, in seconds.
+    pub max_extrapolation: f32,
+    /// Movements between two snapshots longer than this are drawn as jumps, e.g. when the ball
+    /// is put back into the center.
+    pub max_distance: f32,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: 0.1,
+            max_extrapolation: 0.25,
+            max_distance: 300.0,
+        }
+    }
+}
+
+/// Offsets further away from the current estimate than this are taken over at once, e.g. after
+/// connecting to another server, in seconds.
+const MAX_CLOCK_CORRECTION: f64 = 1.0;
+
+/// How fast the estimated offset drifts towards later arrivals, in seconds per second.
+const CLOCK_DECAY: f64 = 0.01;
+
+/// Estimate of the server simulation time on the local clock.
+#[derive(Resource, Default)]
+pub struct ServerClock {
+    /// Server time minus local time, `None` until the first tick arrived.
+    offset: Option<f64>,
+    /// Local time of the last arrived tick.
+    last_arrival: f64,
+}
+
+impl ServerClock {
+    /// Updates the estimate with a tick of `server_time` that arrived at local time `now`.
+    ///
+    /// The fastest arrival gives the closest estimate, so the offset only grows with new ticks
+    /// and slowly shrinks otherwise to follow a drifting clock.
+    pub fn observe(&mut self, server_time: f64, now: f64) {
+        let offset = server_time - now;
+        self.offset = Some(match self.offset {
+            Some(current) if (offset - current).abs() <= MAX_CLOCK_CORRECTION => {
+                offset.max(current - CLOCK_DECAY * (now - self.last_arrival))
+            }
+            _ => offset,
+        });
+        self.last_arrival = now;
+    }
+
+    /// Returns the estimated server time at local time `now`.
+    pub fn server_time(&self, now: f64) -> Option<f64> {
+        self.offset.map(|offset| now + offset)
+    }
+}
+
+/// Received positions of an entity with the server time they describe.
+#[derive(Component, Default)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<(f64, Vec2)>,
+}
+
+impl SnapshotBuffer {
+    pub fn push(&mut self, time: f64, position: Vec2) {
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((time, position));
+    }
+
+    /// Returns the position at `time`, interpolated or extrapolated from the snapshots.
+    ///
+    /// Snapshots that are no longer needed for later times are dropped.
+    pub fn sample(&mut self, time: f64, settings: &InterpolationSettings) -> Option<Vec2> {
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= time {
+            self.snapshots.pop_front();
+        }
+
+        // After dropping old snapshots the first two surround `time`, unless no newer
+        // snapshot arrived yet.
+        let &(first_time, first) = self.snapshots.front()?;
+        let Some(&(next_time, next)) = self.snapshots.get(1) else {
+            return Some(first);
+        };
+        if time <= first_time || next_time <= first_time {
+            return Some(first);
+        }
+        if first.distance(next) > settings.max_distance {
+            return Some(if time < next_time { first } else { next });
+        }
+
+        // Past the newer snapshot the same interpolation extrapolates, for a limited time.
+        let time = time.min(next_time + settings.max_extrapolation as f64);
+        let t = ((time - first_time) / (next_time - first_time)) as f32;
+        Some(first.lerp(next, t))
+    }
+}
+
+/// Position a remote entity is drawn at.
+#[derive(Clone, Copy, Component, Debug, Deref, DerefMut)]
+pub struct InterpolatedPosition(pub Vec2);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(snapshots: &[(f64, f32)]) -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::default();
+        for &(time, x) in snapshots {
+            buffer.push(time, Vec2::new(x, 0.0));
+        }
+        buffer
+    }
+
+    fn sample_x(buffer: &mut SnapshotBuffer, time: f64) -> Option<f32> {
+        buffer
+            .sample(time, &InterpolationSettings::default())
+            .map(|position| position.x)
+    }
+
+    fn assert_near(value: Option<f32>, expected: f32) {
+        let value = value.expect("buffer should have a position");
+        assert!((value - expected).abs() < 1e-3, "{value} != {expected}");
+    }
+
+    #[test]
+    fn empty_buffer_has_no_position() {
+        assert_eq!(sample_x(&mut buffer(&[]), 1.0), None);
+    }
+
+    #[test]
+    fn single_snapshot_is_held() {
+        let mut buffer = buffer(&[(1.0, 10.0)]);
+        assert_near(sample_x(&mut buffer, 0.5), 10.0);
+        assert_near(sample_x(&mut buffer, 2.0), 10.0);
+    }
+
+    #[test]
+    fn position_is_interpolated_between_snapshots() {
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 10.0)]);
+        assert_near(sample_x(&mut buffer, 1.025), 2.5);
+        assert_near(sample_x(&mut buffer, 1.05), 5.0);
+        assert_near(sample_x(&mut buffer, 1.1), 10.0);
+    }
+
+    #[test]
+    fn times_before_the_first_snapshot_are_clamped() {
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 10.0)]);
+        assert_near(sample_x(&mut buffer, 0.5), 0.0);
+    }
+
+    #[test]
+    fn extrapolation_is_limited() {
+        let settings = InterpolationSettings::default();
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 10.0)]);
+        assert_near(sample_x(&mut buffer, 1.15), 15.0);
+
+        let limit = 10.0 + settings.max_extrapolation * 100.0;
+        assert_near(sample_x(&mut buffer, 5.0), limit);
+    }
+
+    #[test]
+    fn long_movements_jump() {
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 1000.0)]);
+        assert_near(sample_x(&mut buffer, 1.05), 0.0);
+        assert_near(sample_x(&mut buffer, 1.1), 1000.0);
+        assert_near(sample_x(&mut buffer, 1.2), 1000.0);
+    }
+
+    #[test]
+    fn passed_snapshots_are_dropped() {
+        let mut buffer = buffer(&[(1.0, 0.0), (1.1, 10.0), (1.2, 20.0)]);
+        assert_near(sample_x(&mut buffer, 1.15), 15.0);
+        assert_eq!(buffer.snapshots.len(), 2);
+
+        // Going back in time can't use the dropped snapshot anymore.
+        assert_near(sample_x(&mut buffer, 1.05), 10.0);
+    }
+
+    #[test]
+    fn oldest_snapshots_are_dropped_when_full() {
+        let mut buffer = SnapshotBuffer::default();
+        for i in 0..MAX_SNAPSHOTS + 10 {
+            buffer.push(i as f64, Vec2::ZERO);
+        }
+
+        assert_eq!(buffer.snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(buffer.snapshots.front().map(|&(time, _)| time), Some(10.0));
+    }
+
+    #[test]
+    fn clock_is_unknown_until_a_tick_arrived() {
+        assert_eq!(ServerClock::default().server_time(1.0), None);
+    }
+
+    #[test]
+    fn first_tick_sets_the_offset() {
+        let mut clock = ServerClock::default();
+        clock.observe(10.0, 2.0);
+        assert_eq!(clock.server_time(3.0), Some(11.0));
+    }
+
+    #[test]
+    fn faster_arrival_raises_the_offset() {
+        let mut clock = ServerClock::default();
+        clock.observe(10.0, 2.0);
+        clock.observe(10.1, 2.05);
+        assert_near(clock.server_time(3.0).map(|time| time as f32), 11.05);
+    }
+
+    #[test]
+    fn slower_arrival_only_decays_the_offset() {
+        let mut clock = ServerClock::default();
+        clock.observe(10.0, 2.0);
+        clock.observe(10.1, 2.2);
+        let expected = 11.0 - CLOCK_DECAY * 0.2;
+        assert_near(
+            clock.server_time(3.0).map(|time| time as f32),
+            expected as f32,
+        );
+    }
+
+    #[test]
+    fn large_corrections_are_taken_over() {
+        let mut clock = ServerClock::default();
+        clock.observe(10.0, 2.0);
+        clock.observe(100.0, 3.0);
+        assert_eq!(clock.server_time(4.0), Some(101.0));
+
+        clock.observe(50.0, 4.0);
+        assert_eq!(clock.server_time(5.0), Some(51.0));
+    }
+}
@@ -1,335 +1,1425 @@
-use bevy::prelude::*;
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, render::camera::ScalingMode, utils::HashMap};
 
 use bevy_egui::{
-    egui::{self, Frame, Pos2, Ui},
+    egui::{self, Align2, Frame, Pos2, Ui},
     EguiContext,
 };
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use bevy_replicon::{
     prelude::*,
-    renet::{ClientId, ServerEvent},
+    renet::{
+        transport::{NetcodeServerTransport, NETCODE_USER_DATA_BYTES},
+        ClientId, ServerEvent,
+    },
 };
 
+pub mod auth;
+pub mod autopilot;
+pub mod bot;
+pub mod hot_seat;
+pub mod interpolation;
+pub mod lobby;
+pub mod physics;
+pub mod playback;
+pub mod prediction;
+pub mod replay;
+pub mod room;
+pub mod rules;
+pub mod scoring;
+pub mod tick;
+pub mod validation;
+pub mod verify;
+
+#[cfg(test)]
+mod tests;
+
+use bot::{BotDifficulty, BotSettings, Bots};
+use hot_seat::HotSeat;
+use interpolation::{InterpolatedPosition, InterpolationSettings, ServerClock, SnapshotBuffer};
+use lobby::{send_to_lobby, Lobby, LobbyMatch};
+use physics::{step_ball, Aabb};
+use prediction::{InputAck, InputHistory, PredictedPosition};
+use replay::ReplayRecorder;
+use room::{send_to_room, CurrentRoom, RoomId, RoomMessage, Rooms};
+use rules::MatchRules;
+use scoring::{Score, Serve, Side};
+use tick::{SimulationTick, TickRate};
+use validation::{ValidationSettings, Violation, Violations};
+
 pub const PORT: u16 = 5000;
 pub const PROTOCOL_ID: u64 = 0;
-const SCREEN_WIDTH: f32 = 1280.0;
-const SCREEN_HEGIHT: f32 = 720.0;
-const PADDLE_WIDTH: f32 = 50.0;
-const PADDLE_HEIGHT: f32 = 250.0;
-const BALL_WIDTH: f32 = 50.0;
-const SPEED: f32 = 150.0;
-const PADDLE_LEFT_X: f32 = SCREEN_WIDTH / 2.0 - PADDLE_WIDTH / 2.0;
-const PADDLE_LEFT_Y: f32 = -(SCREEN_WIDTH / 2.0 - PADDLE_WIDTH / 2.0);
-const CLAMP_MAX_PADDLE_Y: f32 = SCREEN_HEGIHT / 2.0 - PADDLE_HEIGHT / 2.0;
-const CLAMP_MIN_PADDLE_Y: f32 = -CLAMP_MAX_PADDLE_Y;
-const CLAMP_MAX_BALL_Y: f32 = SCREEN_HEGIHT / 2.0 - BALL_WIDTH / 2.0;
-const CLAMP_MIN_BALL_Y: f32 = -CLAMP_MAX_BALL_Y;
-const CLAMP_MAX_BALL_X: f32 = SCREEN_WIDTH / 2.0 - BALL_WIDTH / 2.0;
-const CLAMP_MIN_BALL_X: f32 = -CLAMP_MAX_BALL_X;
-#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Deserialize, Hash, Serialize, States)]
 enum GameState {
     #[default]
     Menu,
     Game,
+    /// A player lost their connection and the match waits for them to rejoin.
+    Paused,
     End,
 }
-#[derive(Resource, Default)]
+/// State of a match.
+///
+/// The server keeps it for every room and replicates it on the room's match entity. Clients copy
+/// the one of their current room into this resource and follow its phase with their game state.
+#[derive(Clone, Component, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
 pub struct GameData {
     player_count: usize,
     actor1: u64,
     actor2: u64,
-    score1: u16,
-    score2: u16,
-    round: u16,
+    /// Players the match is waiting for to reconnect.
+    absent: Vec<u64>,
+    /// Connected clients that watch the match without a paddle.
+    spectators: Vec<u64>,
+    phase: GameState,
+    /// Players who want to play the finished match again.
+    rematch_votes: Vec<u64>,
+    /// Players who left the finished match.
+    left: Vec<u64>,
+    /// Player who won because the opponent didn't reconnect.
+    forfeit_winner: Option<u64>,
 }
 
+impl GameData {
+    /// Returns whether the client plays the match and hasn't left it.
+    fn is_player(&self, client_id: u64) -> bool {
+        !self.is_spectator(client_id) && !self.left.contains(&client_id)
+    }
+
+    /// Returns whether the client watches the match instead of playing it.
+    fn is_spectator(&self, client_id: u64) -> bool {
+        client_id != self.actor1 && client_id != self.actor2
+    }
+
+    /// Returns the winner of a finished match, `None` for a draw.
+    fn winner(&self, score: &Score) -> Option<u64> {
+        if self.forfeit_winner.is_some() {
+            return self.forfeit_winner;
+        }
+        score.leader().map(|side| self.actor_of(side))
+    }
+
+    /// Client id of the player on a side of the field.
+    fn actor_of(&self, side: Side) -> u64 {
+        match side {
+            Side::Left => self.actor1,
+            Side::Right => self.actor2,
+        }
+    }
+}
+
+/// Slots of disconnected players held until they reconnect, keyed by client id.
+#[derive(Default, Deref, DerefMut)]
+struct ReconnectTimers(HashMap<u64, Timer>);
+
+/// Inputs queued more than this per client are dropped, oldest first.
+const MAX_QUEUED_INPUTS: usize = 8;
+
+/// Inputs received from clients waiting for the next simulation tick, keyed by client id.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct InputQueues(HashMap<ClientId, VecDeque<MoveDirection>>);
+
+/// Inputs the last simulated tick moved the paddles with, as room, client id and direction.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct AppliedInputs(Vec<(RoomId, u64, Vec2)>);
+
 #[derive(Resource)]
 pub struct LocalData {
     pub client_id: u64,
 }
 
-pub struct PingPongPlugin;
+/// Keys that move the paddle of the local player.
+#[derive(Resource)]
+pub struct PaddleKeys {
+    pub up: KeyCode,
+    pub down: KeyCode,
+}
+
+impl Default for PaddleKeys {
+    fn default() -> Self {
+        Self {
+            up: KeyCode::Up,
+            down: KeyCode::Down,
+        }
+    }
+}
+
+/// The player of a client that hosts the game itself.
+///
+/// The host plays with the client id of the server and isn't connected through renet. Its
+/// inputs and messages are passed to the simulation within the app.
+#[derive(Resource)]
+pub struct HostPlayer {
+    pub name: String,
+}
+
+/// Packs a player name into netcode user data as a length-prefixed UTF-8 string.
+///
+/// Names that don't fit are truncated on a character boundary.
+pub fn name_to_user_data(name: &str) -> [u8; NETCODE_USER_DATA_BYTES] {
+    let mut len = name.len().min(NETCODE_USER_DATA_BYTES - 1);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
 
-impl Plugin for PingPongPlugin {
+    let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+    user_data[0] = len as u8;
+    user_data[1..=len].copy_from_slice(&name.as_bytes()[..len]);
+    user_data
+}
+
+/// Reads a player name written by [`name_to_user_data`].
+pub fn name_from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> String {
+    let len = user_data[0] as usize;
+    String::from_utf8_lossy(&user_data[1..=len]).into_owned()
+}
+
+/// Sends a message to each of the given clients.
+pub(crate) fn send_to_clients(
+    game_message_events: &mut EventWriter<ToClients<ServerMessage>>,
+    clients: impl IntoIterator<Item = u64>,
+    msg: S2cMessage,
+) {
+    for client_id in clients
+        .into_iter()
+        .filter(|&client_id| !bot::is_bot(client_id))
+    {
+        game_message_events.send(ToClients {
+            mode: SendMode::Direct(ClientId::from_raw(client_id)),
+            event: ServerMessage { msg: msg.clone() },
+        });
+    }
+}
+
+/// Generates a pseudo random paddle color from the client id.
+fn player_color(client_id: u64) -> Color {
+    let r = ((client_id % 23) as f32) / 23.0;
+    let g = ((client_id % 27) as f32) / 27.0;
+    let b = ((client_id % 39) as f32) / 39.0;
+    Color::rgb(r, g, b)
+}
+
+/// Types and resources used by both the server and the clients.
+///
+/// Registers the replicated components and the network events, so it has to be added to every
+/// app together with the [`ReplicationPlugins`] and either [`PingPongServerPlugin`] or
+/// [`PingPongClientPlugin`].
+pub struct PingPongSharedPlugin;
+
+impl Plugin for PingPongSharedPlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>()
-            .replicate::<PlayerPosition>()
+        app.replicate::<PlayerPosition>()
             .replicate::<PlayerColor>()
             .replicate::<Ball>()
             .replicate::<Paddle>()
-            .insert_resource::<GameData>(GameData {
-                player_count: 0,
-                actor1: 0,
-                actor2: 0,
-                score1: 0,
-                score2: 0,
-                round: 0,
-            })
+            .replicate::<Player>()
+            .replicate::<PlayerName>()
+            .replicate::<MatchRules>()
+            .replicate::<RoomId>()
+            .replicate::<InputAck>()
+            .replicate::<SimulationTick>()
+            .replicate::<Score>()
+            .replicate::<Serve>()
+            .replicate::<GameData>()
+            .init_resource::<MatchRules>()
+            .init_resource::<Lobby>()
+            .init_resource::<TickRate>()
             .add_client_event::<MoveDirection>(EventType::Ordered)
+            .add_client_event::<ClientMessage>(EventType::Ordered)
             .add_server_event::<ServerMessage>(EventType::Ordered)
-            .add_systems(Startup, (Self::init_system,))
             .add_systems(
-                OnEnter(GameState::Game),
-                (Self::notify_game_state).run_if(resource_exists::<RenetServer>()),
+                Update,
+                Self::apply_tick_rate_system.run_if(resource_changed::<TickRate>()),
+            );
+    }
+}
+
+impl PingPongSharedPlugin {
+    fn apply_tick_rate_system(tick_rate: Res<TickRate>, mut time: ResMut<Time<Fixed>>) {
+        time.set_timestep_hz(tick_rate.0.into());
+    }
+}
+
+/// The headless game server, simulates the matches of all rooms.
+///
+/// Its systems only run once the [`Rooms`] are inserted, so a client can add the plugin and
+/// start hosting later. The [`MatchRules`], the [`TickRate`] and the renet server resources
+/// have to be inserted together with the rooms at the latest.
+pub struct PingPongServerPlugin;
+
+/// Systems of the [`PingPongServerPlugin`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq, SystemSet)]
+struct ServerSet;
+
+impl Plugin for PingPongServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputQueues>()
+            .init_resource::<Violations>()
+            .init_resource::<ValidationSettings>()
+            .init_resource::<AppliedInputs>()
+            .init_resource::<Bots>()
+            .init_resource::<BotSettings>()
+            .add_event::<RoomMessage>()
+            .configure_sets(FixedUpdate, ServerSet.run_if(resource_exists::<Rooms>()))
+            .configure_sets(Update, ServerSet.run_if(resource_exists::<Rooms>()))
+            .add_systems(
+                FixedUpdate,
+                (
+                    bot::input_system,
+                    Self::movement_system,
+                    Self::advance_tick_system,
+                    replay::record_ticks_system.run_if(resource_exists::<ReplayRecorder>()),
+                )
+                    .chain()
+                    .in_set(ServerSet),
             )
             .add_systems(
                 Update,
                 (
-                    Self::movement_system
-                        .run_if(has_authority())
-                        .run_if(in_state(GameState::Game)),
-                    Self::server_event_system.run_if(resource_exists::<RenetServer>()),
-                    Self::client_event_system.run_if(resource_exists::<RenetClient>()),
-                    (Self::draw_boxes_system, Self::input_system)
-                        .run_if(not(in_state(GameState::Menu)))
-                        .run_if(resource_exists::<RenetClient>()),
-                ),
+                    (
+                        Self::queue_inputs_system,
+                        validation::forgive_system,
+                        validation::kick_system.run_if(resource_exists::<RenetServer>()),
+                    )
+                        .chain(),
+                    (
+                        (
+                            Self::init_system,
+                            Self::enter_host_system.run_if(resource_exists::<HostPlayer>()),
+                        )
+                            .run_if(resource_added::<Rooms>()),
+                        Self::server_event_system
+                            .run_if(resource_exists::<NetcodeServerTransport>()),
+                        Self::lobby_system,
+                        Self::room_system,
+                        bot::room_system,
+                        Self::reconnect_timeout_system,
+                        Self::close_deserted_rooms_system,
+                        bot::fill_queue_system,
+                        Self::matchmaking_system,
+                        Self::publish_match_state_system,
+                        room::visibility_system.run_if(resource_exists::<RenetServer>()),
+                        replay::record_messages_system.run_if(resource_exists::<ReplayRecorder>()),
+                    )
+                        .chain(),
+                )
+                    .in_set(ServerSet),
             );
     }
 }
 
-impl PingPongPlugin {
-    fn init_system(mut commands: Commands) {
-        commands.spawn(Camera2dBundle {
-            camera: Camera {
-                order: -1,
-                ..default()
+impl PingPongServerPlugin {
+    fn init_system(mut commands: Commands, rules: Res<MatchRules>, tick_rate: Res<TickRate>) {
+        commands.spawn((
+            rules.clone(),
+            SimulationTick {
+                tick: 0,
+                rate: tick_rate.0,
             },
-            ..default()
-        });
-        commands.insert_resource(SplashTimer(Timer::from_seconds(
-            1.0 / 60.0,
-            TimerMode::Repeating,
-        )));
+            Replication,
+        ));
     }
 
-    pub fn init_system_server(mut commands: Commands) {
-        println!("spawn ball");
-        commands.spawn(BallBundle::new(Vec2::ZERO, Color::rgb(1.0, 1.0, 1.0)));
+    /// Puts the player of a hosting client into the lobby, like a client that just connected.
+    fn enter_host_system(
+        host: Res<HostPlayer>,
+        mut lobby: ResMut<Lobby>,
+        mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+    ) {
+        info!("hosting as {}", host.name);
+        Self::enter_lobby(
+            &mut lobby,
+            &mut game_message_events,
+            SERVER_ID.raw(),
+            host.name.clone(),
+        );
     }
 
-    fn client_event_system(
-        mut move_events: EventReader<ServerMessage>,
-        mut game_state: ResMut<NextState<GameState>>,
-        mut game_data: ResMut<GameData>,
+    /// Validates the received inputs and queues them for the simulation.
+    ///
+    /// Events only live for two frames, so reading them in `FixedUpdate` would lose the inputs
+    /// of frames without a tick.
+    fn queue_inputs_system(
+        rooms: Res<Rooms>,
+        mut queues: ResMut<InputQueues>,
+        mut violations: ResMut<Violations>,
+        mut move_events: EventReader<FromClient<MoveDirection>>,
     ) {
-        for event in move_events.read() {
-            match event.msg {
-                S2cMessage::None => {}
-                S2cMessage::GameStart(actor1_id, actor2_id) => {
-                    game_state.set(GameState::Game);
-                    game_data.actor1 = actor1_id;
-                    game_data.actor2 = actor2_id;
-                }
-                S2cMessage::ClientJoin(client_id, client_actor_id) => {
-                    if (client_actor_id == 1) {
-                        game_data.actor1 = client_id;
-                    } else {
-                        game_data.actor2 = client_id;
-                    }
-                }
-                S2cMessage::RoundResult(client_actor_id) => {
-                    if (client_actor_id == 1) {
-                        game_data.score1 += 1;
-                    } else {
-                        game_data.score2 += 1;
-                    }
-                    game_data.round += 1;
-                }
-                S2cMessage::GameEnd => game_state.set(GameState::End),
+        for FromClient { client_id, event } in move_events.read() {
+            let raw_id = client_id.raw();
+            let is_player = rooms
+                .room_of(raw_id)
+                .and_then(|room_id| rooms.get(room_id))
+                .is_some_and(|room| room.game_data.is_player(raw_id));
+            if !is_player {
+                violations.record(raw_id, Violation::NotPlaying);
+                continue;
+            }
+            let Some(direction) = validation::sanitize_direction(event.direction) else {
+                violations.record(raw_id, Violation::InvalidDirection);
+                continue;
+            };
+            if direction != event.direction {
+                violations.record(raw_id, Violation::InvalidDirection);
             }
+
+            let queue = queues.entry(*client_id).or_default();
+            if queue.len() == MAX_QUEUED_INPUTS {
+                queue.pop_front();
+                violations.record(raw_id, Violation::TooManyInputs);
+            }
+            queue.push_back(MoveDirection {
+                direction,
+                sequence: event.sequence,
+            });
         }
     }
+
+    /// Stamps the state of the finished tick with the next tick number for replication.
+    fn advance_tick_system(
+        mut replicon_tick: ResMut<RepliconTick>,
+        mut ticks: Query<&mut SimulationTick>,
+    ) {
+        replicon_tick.increment();
+        for mut tick in &mut ticks {
+            tick.tick = replicon_tick.get();
+        }
+    }
+
+    /// Simulates one tick of every match.
     fn movement_system(
-        time: Res<Time>,
-        mut game_date: ResMut<GameData>,
-        mut move_events: EventReader<FromClient<MoveDirection>>,
-        mut paddles: Query<(&Player, &mut PlayerPosition), (With<Paddle>, Without<Ball>)>,
-        mut ball: Query<(&mut PlayerPosition, &mut PlayerSpeed), (With<Ball>, Without<Paddle>)>,
-        mut nextState: ResMut<NextState<GameState>>,
+        time: Res<Time<Fixed>>,
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        replicon_tick: Res<RepliconTick>,
+        mut rooms: ResMut<Rooms>,
+        mut queues: ResMut<InputQueues>,
+        mut applied_inputs: ResMut<AppliedInputs>,
+        mut paddles: Query<
+            (Entity, &Player, &RoomId, &mut PlayerPosition, &mut InputAck),
+            (With<Paddle>, Without<Ball>),
+        >,
+        mut balls: Query<
+            (&RoomId, &mut PlayerPosition, &mut PlayerSpeed),
+            (With<Ball>, Without<Paddle>),
+        >,
+        mut matches: Query<(&RoomId, &mut Score, &mut Serve)>,
         mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+        mut room_messages: EventWriter<RoomMessage>,
     ) {
-        const MOVE_SPEED: f32 = SPEED;
-        for FromClient { client_id, event } in move_events.read() {
-            for (player, mut position) in &mut paddles {
-                if *client_id == player.0 {
-                    let f = event.0 * 1.0 * MOVE_SPEED / 60.0;
-                    **position += f;
-                    position.y = position.y.clamp(CLAMP_MIN_PADDLE_Y, CLAMP_MAX_PADDLE_Y);
+        let dt = time.delta_seconds();
+        // Vertical velocity of the paddles moved in this tick, they put spin on the ball.
+        let mut paddle_velocities = HashMap::<Entity, f32>::default();
+
+        // A client moves its paddle by at most one input per tick.
+        applied_inputs.clear();
+        queues.retain(|_, queue| !queue.is_empty());
+        for (client_id, queue) in queues.iter_mut() {
+            let Some(event) = queue.pop_front() else {
+                continue;
+            };
+            let Some(client_room_id) = rooms.room_of(client_id.raw()) else {
+                continue;
+            };
+            let is_playing = rooms
+                .get(client_room_id)
+                .is_some_and(|room| room.game_data.phase == GameState::Game);
+            for (entity, player, room_id, mut position, mut ack) in &mut paddles {
+                if *client_id != player.0 || *room_id != client_room_id {
+                    continue;
+                }
+                // Inputs are acknowledged even while the match is paused, so the client stops
+                // replaying them.
+                if is_playing {
+                    let moved = rules.move_paddle(**position, event.direction, dt);
+                    paddle_velocities.insert(entity, (moved.y - position.y) / dt);
+                    **position = moved;
+                    applied_inputs.push((client_room_id, client_id.raw(), event.direction));
                 }
+                ack.0 = event.sequence;
             }
         }
 
-        let (mut ball_pos, mut ball_velocivy) = ball.single_mut();
-        ball_pos.x = ball_pos.x + ball_velocivy.x * time.delta_seconds();
-        ball_pos.y = ball_pos.y + ball_velocivy.y * time.delta_seconds();
-        // if (ball_pos.translation.x <= CLAMP_MIN_BALL_X || ball_pos.translation.x >= CLAMP_MAX_BALL_X)
-        // {
-        //     game_state.set(GameState::End);
-        // }
-        if ball_pos.y <= CLAMP_MIN_BALL_Y {
-            ball_velocivy.y = -ball_velocivy.y;
-        } else if ball_pos.y >= CLAMP_MAX_BALL_Y {
-            ball_velocivy.y = -ball_velocivy.y;
-        }
-
-        ball_pos.y = f32::clamp(ball_pos.y, CLAMP_MIN_BALL_Y, CLAMP_MAX_BALL_Y);
-        let mut is_reset: bool = false;
-        if (ball_pos.x <= CLAMP_MIN_BALL_X && game_date.round < 3) {
-            ball_pos.x = 0.0;
-            ball_pos.y = 0.0;
-            ball_velocivy.x = -ball_velocivy.x;
-            game_date.round += 1;
-            game_date.score1 += 1;
-            game_message_events.send(ToClients {
-                mode: SendMode::Broadcast,
-                event: ServerMessage {
-                    msg: S2cMessage::RoundResult(2),
-                },
-            });
-            is_reset = true;
-        } else if (ball_pos.x >= CLAMP_MAX_BALL_X && game_date.round < 3) {
-            ball_pos.x = 0.0;
-            ball_pos.y = 0.0;
-            ball_velocivy.x = -ball_velocivy.x;
-            game_date.round += 1;
-            game_date.score2 += 1;
-            game_message_events.send(ToClients {
-                mode: SendMode::Broadcast,
-                event: ServerMessage {
-                    msg: S2cMessage::RoundResult(1),
-                },
-            });
-            is_reset = true;
-        }
+        for (room_id, mut ball_pos, mut ball_velocivy) in &mut balls {
+            let Some(room) = rooms.get_mut(*room_id) else {
+                continue;
+            };
+            if room.game_data.phase != GameState::Game {
+                continue;
+            }
+            let Some((_, mut score, mut serve)) =
+                matches.iter_mut().find(|(id, ..)| *id == room_id)
+            else {
+                continue;
+            };
+            if !serve.is_due(replicon_tick.get()) {
+                continue;
+            }
+            let game_date = &mut room.game_data;
 
-        if (is_reset && game_date.round >= 3) {
-            println!("game end: {}", game_date.round);
-            nextState.set(GameState::End);
-            game_message_events.send(ToClients {
-                mode: SendMode::Broadcast,
-                event: ServerMessage {
-                    msg: S2cMessage::GameEnd,
+            let ball_max = rules.ball_max();
+            let (room_paddles, room_paddle_velocities): (Vec<_>, Vec<_>) = paddles
+                .iter()
+                .filter(|(_, _, paddle_room_id, ..)| *paddle_room_id == room_id)
+                .map(|(entity, _, _, position, _)| {
+                    (
+                        Aabb::new(**position, rules.paddle_size),
+                        paddle_velocities.get(&entity).copied().unwrap_or_default(),
+                    )
+                })
+                .unzip();
+            let ball = Aabb::new(**ball_pos, Vec2::splat(rules.ball_size));
+            let step = step_ball(
+                ball,
+                **ball_velocivy,
+                dt,
+                ball_max.y,
+                &room_paddles,
+                |index, position, velocity| {
+                    let paddle = room_paddles[index];
+                    let reach = paddle.half_size.y + ball.half_size.y;
+                    let offset = (position.y - paddle.center.y) / reach;
+                    rules.paddle_bounce(velocity, offset, room_paddle_velocities[index])
                 },
-            });
-        }
+            );
+            **ball_pos = step.position;
+            **ball_velocivy = step.velocity;
 
-        for (_player, position) in &paddles {
-            let collision = Self::intersect(
-                Vec2::new(ball_pos.x, ball_pos.y),
-                Vec2::new(BALL_WIDTH, BALL_WIDTH),
-                Vec2::new(position.x, position.y),
-                Vec2::new(PADDLE_WIDTH, PADDLE_HEIGHT),
+            if score.is_match_over(&rules) {
+                continue;
+            }
+            let Some(conceding) = scoring::conceding_side(ball_pos.x, &rules) else {
+                continue;
+            };
+            let scorer = score.award_point(conceding);
+            send_to_room(
+                &mut game_message_events,
+                &mut room_messages,
+                *room_id,
+                game_date,
+                S2cMessage::RoundResult(scorer.actor()),
             );
-            if collision {
-                ball_velocivy.x = -ball_velocivy.x;
-                if ball_pos.x < 0.0 {
-                    ball_velocivy.x = ball_velocivy.x.abs()
-                } else {
-                    ball_velocivy.x = -ball_velocivy.x.abs()
-                }
+            **ball_pos = Vec2::ZERO;
+            **ball_velocivy = rules.serve_velocity(&mut room.rng, conceding.direction());
+            *serve = Serve::after(replicon_tick.get(), &rules, *tick_rate);
+
+            if score.is_match_over(&rules) {
+                info!("game end in room {}: {}", room_id.0, score.round);
+                game_date.phase = GameState::End;
+                send_to_room(
+                    &mut game_message_events,
+                    &mut room_messages,
+                    *room_id,
+                    game_date,
+                    S2cMessage::GameEnd,
+                );
             }
         }
     }
 
-    fn intersect(center_a: Vec2, size_a: Vec2, center_b: Vec2, size_b: Vec2) -> bool {
-        return center_a.x - size_a.x / 2.0 <= center_b.x + size_b.x / 2.0
-            && center_a.x + size_a.x / 2.0 >= center_b.x - size_b.x / 2.0
-            && center_a.y - size_a.y / 2.0 <= center_b.y + size_b.y / 2.0
-            && center_a.y + size_a.y / 2.0 >= center_b.y - size_b.y / 2.0;
-    }
-
     fn server_event_system(
-        mut commands: Commands,
         mut server_event: EventReader<ServerEvent>,
-        mut game_state: ResMut<NextState<GameState>>,
-        mut game_data: ResMut<GameData>,
+        mut rooms: ResMut<Rooms>,
+        mut lobby: ResMut<Lobby>,
         mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+        mut room_messages: EventWriter<RoomMessage>,
+        mut server: ResMut<RenetServer>,
+        transport: Res<NetcodeServerTransport>,
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        replicon_tick: Res<RepliconTick>,
+        mut serves: Query<(&RoomId, &mut Serve)>,
     ) {
         for event in server_event.read() {
             match event {
                 ServerEvent::ClientConnected { client_id } => {
-                    info!("player: {client_id} Connected");
-                    // Generate pseudo random color from client id.
-                    let r = ((client_id.raw() % 23) as f32) / 23.0;
-                    let g = ((client_id.raw() % 27) as f32) / 27.0;
-                    let b = ((client_id.raw() % 39) as f32) / 39.0;
-                    if game_data.player_count == 0 {
-                        commands.spawn(PlayerBundle::new(
-                            *client_id,
-                            Vec2::new(-PADDLE_LEFT_X, 0.0),
-                            Color::rgb(r, g, b),
-                        ));
-                        game_message_events.send(ToClients {
-                            mode: SendMode::Broadcast,
-                            event: ServerMessage {
-                                msg: S2cMessage::ClientJoin(client_id.raw(), 1),
-                            },
-                        });
-                        game_data.actor1 = client_id.raw();
-                        game_data.player_count += 1;
-                    } else if game_data.player_count == 1 {
-                        commands.spawn(PlayerBundle::new(
-                            *client_id,
-                            Vec2::new(PADDLE_LEFT_X, 0.0),
-                            Color::rgb(r, g, b),
-                        ));
-
-                        game_message_events.send(ToClients {
-                            mode: SendMode::Broadcast,
-                            event: ServerMessage {
-                                msg: S2cMessage::ClientJoin(client_id.raw(), 2),
-                            },
-                        });
-                        game_data.actor2 = client_id.raw();
-                        game_data.player_count += 1;
+                    let raw_id = client_id.raw();
+                    if bot::is_bot(raw_id) {
+                        warn!("disconnecting {client_id}, the id is reserved for bots");
+                        server.disconnect(*client_id);
+                        continue;
                     }
-
-                    if game_data.player_count == 2 {
-                        game_state.set(GameState::Game);
-                        game_message_events.send(ToClients {
-                            mode: SendMode::Broadcast,
-                            event: ServerMessage {
-                                msg: S2cMessage::GameStart(game_data.actor1, game_data.actor2),
-                            },
-                        });
-                        println!("send gamestart");
+                    if let Some(room_id) = rooms.room_of(raw_id) {
+                        let room = rooms.get_mut(room_id).expect("client room should exist");
+                        if room.reconnect_timers.remove(&raw_id).is_some() {
+                            info!("player: {client_id} rejoined room {}", room_id.0);
+                            // Still marked absent, so the rejoined player is left out.
+                            send_to_room(
+                                &mut game_message_events,
+                                &mut room_messages,
+                                room_id,
+                                &room.game_data,
+                                S2cMessage::PlayerReconnected(raw_id),
+                            );
+                            room.game_data.absent.retain(|&id| id != raw_id);
+                            Self::send_joined_room(*client_id, room_id, &mut game_message_events);
+                            if room.reconnect_timers.is_empty() {
+                                room.game_data.phase = GameState::Game;
+                                // The ball waits where it is until the countdown is over again.
+                                for (_, mut serve) in
+                                    serves.iter_mut().filter(|(id, _)| **id == room_id)
+                                {
+                                    *serve = Serve::after(replicon_tick.get(), &rules, *tick_rate);
+                                }
+                            }
+                            continue;
+                        }
                     }
+
+                    let name = Self::client_name(Some(&transport), *client_id);
+                    info!("player: {client_id} ({name}) Connected");
+                    Self::enter_lobby(&mut lobby, &mut game_message_events, raw_id, name);
                 }
                 ServerEvent::ClientDisconnected { client_id, reason } => {
                     info!("client {client_id} disconnected: {reason}");
+                    let raw_id = client_id.raw();
+                    if lobby.contains(raw_id) {
+                        lobby.leave(raw_id);
+                        send_to_lobby(
+                            &mut game_message_events,
+                            &lobby,
+                            S2cMessage::LobbyLeft(raw_id),
+                        );
+                        continue;
+                    }
+                    let Some(room_id) = rooms.room_of(raw_id) else {
+                        continue;
+                    };
+                    let room = rooms.get_mut(room_id).expect("client room should exist");
+                    let game_data = &mut room.game_data;
+                    if game_data.is_spectator(raw_id) {
+                        game_data.spectators.retain(|&id| id != raw_id);
+                        rooms.leave(raw_id);
+                        continue;
+                    }
+
+                    match game_data.phase {
+                        GameState::Game | GameState::Paused => {
+                            info!(
+                                "holding the slot of {client_id} for {}s",
+                                rules.reconnect_grace_secs
+                            );
+                            room.reconnect_timers.insert(
+                                raw_id,
+                                Timer::from_seconds(rules.reconnect_grace_secs, TimerMode::Once),
+                            );
+                            game_data.phase = GameState::Paused;
+                            game_data.absent.push(raw_id);
+                            send_to_room(
+                                &mut game_message_events,
+                                &mut room_messages,
+                                room_id,
+                                game_data,
+                                S2cMessage::PlayerDisconnected(raw_id),
+                            );
+                        }
+                        GameState::Menu | GameState::End => {
+                            Self::free_slot(game_data, raw_id);
+                            send_to_room(
+                                &mut game_message_events,
+                                &mut room_messages,
+                                room_id,
+                                game_data,
+                                S2cMessage::PlayerLeft(raw_id),
+                            );
+                            rooms.leave(raw_id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the name a client connected with.
+    fn client_name(transport: Option<&NetcodeServerTransport>, client_id: ClientId) -> String {
+        transport
+            .and_then(|transport| transport.user_data(client_id))
+            .map(|user_data| name_from_user_data(&user_data))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("Player {client_id}"))
+    }
+
+    /// Adds a client to the lobby and sends it the lobby state.
+    fn enter_lobby(
+        lobby: &mut Lobby,
+        game_message_events: &mut EventWriter<ToClients<ServerMessage>>,
+        client_id: u64,
+        name: String,
+    ) {
+        send_to_lobby(
+            game_message_events,
+            lobby,
+            S2cMessage::LobbyJoined(client_id, name.clone()),
+        );
+        lobby.enter(client_id, name);
+        for msg in lobby.state_messages() {
+            send_to_clients(game_message_events, [client_id], msg);
+        }
+    }
+
+    fn send_joined_room(
+        client_id: ClientId,
+        room_id: RoomId,
+        game_message_events: &mut EventWriter<ToClients<ServerMessage>>,
+    ) {
+        if bot::is_bot(client_id.raw()) {
+            return;
+        }
+        game_message_events.send(ToClients {
+            mode: SendMode::Direct(client_id),
+            event: ServerMessage {
+                msg: S2cMessage::JoinedRoom(room_id.0),
+            },
+        });
+    }
+
+    /// Forfeits a match once the grace periods of all disconnected players ran out.
+    ///
+    /// While another absent player may still rejoin, the match keeps waiting for them. If both
+    /// players are gone, the match ends without a winner.
+    fn reconnect_timeout_system(
+        time: Res<Time>,
+        mut rooms: ResMut<Rooms>,
+        mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+        mut room_messages: EventWriter<RoomMessage>,
+    ) {
+        let mut freed = Vec::new();
+        for (room_id, room) in rooms.iter_mut() {
+            if room.reconnect_timers.is_empty() {
+                continue;
+            }
+            for timer in room.reconnect_timers.values_mut() {
+                timer.tick(time.delta());
+            }
+            if !room.reconnect_timers.values().all(|timer| timer.finished()) {
+                continue;
+            }
+
+            let game_data = &mut room.game_data;
+            let winner_id = [game_data.actor1, game_data.actor2]
+                .into_iter()
+                .find(|id| !room.reconnect_timers.contains_key(id));
+            match winner_id {
+                Some(winner_id) => info!(
+                    "{winner_id} wins room {} by forfeit, the opponent did not reconnect",
+                    room_id.0
+                ),
+                None => info!("no player reconnected to room {}", room_id.0),
+            }
+            for (absent_id, _) in room.reconnect_timers.drain() {
+                Self::free_slot(game_data, absent_id);
+                freed.push(absent_id);
+            }
+            game_data.absent.clear();
+            game_data.forfeit_winner = winner_id;
+
+            game_data.phase = GameState::End;
+            for msg in winner_id
+                .map(S2cMessage::Forfeit)
+                .into_iter()
+                .chain([S2cMessage::GameEnd])
+            {
+                send_to_room(
+                    &mut game_message_events,
+                    &mut room_messages,
+                    room_id,
+                    game_data,
+                    msg,
+                );
+            }
+        }
+        for client_id in freed {
+            rooms.leave(client_id);
+        }
+    }
+
+    fn close_deserted_rooms_system(
+        mut commands: Commands,
+        mut rooms: ResMut<Rooms>,
+        mut lobby: ResMut<Lobby>,
+        mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+        entities: Query<(Entity, &RoomId)>,
+    ) {
+        for room_id in rooms.close_deserted() {
+            for (entity, _) in entities.iter().filter(|(_, id)| **id == room_id) {
+                commands.entity(entity).despawn();
+            }
+            lobby.close_match(room_id);
+            send_to_lobby(
+                &mut game_message_events,
+                &lobby,
+                S2cMessage::MatchClosed(room_id.0),
+            );
+        }
+    }
+
+    /// Handles the lobby requests of clients waiting for a match.
+    fn lobby_system(
+        mut lobby: ResMut<Lobby>,
+        mut rooms: ResMut<Rooms>,
+        mut bots: ResMut<Bots>,
+        mut client_messages: EventReader<FromClient<ClientMessage>>,
+        mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+    ) {
+        let mut dropped = Vec::new();
+        for FromClient { client_id, event } in client_messages.read() {
+            let raw_id = client_id.raw();
+            if !lobby.contains(raw_id) {
+                continue;
+            }
+            match event.msg {
+                C2sMessage::None | C2sMessage::Rematch | C2sMessage::Leave => {}
+                C2sMessage::SetReady(ready) => {
+                    lobby.set_ready(raw_id, ready);
+                    send_to_lobby(
+                        &mut game_message_events,
+                        &lobby,
+                        S2cMessage::LobbyReady(raw_id, ready),
+                    );
+                }
+                C2sMessage::Challenge(challenged) => {
+                    if challenged == raw_id
+                        || bot::is_bot(challenged)
+                        || !lobby.contains(challenged)
+                        || lobby.is_paired(raw_id)
+                        || lobby.is_paired(challenged)
+                    {
+                        continue;
+                    }
+                    lobby.add_challenge(raw_id, challenged);
+                    send_to_clients(
+                        &mut game_message_events,
+                        [raw_id, challenged],
+                        S2cMessage::Challenge(raw_id, challenged),
+                    );
+                }
+                C2sMessage::AcceptChallenge(challenger) => {
+                    if lobby.remove_challenge(challenger, raw_id) {
+                        info!("{raw_id} accepted the challenge of {challenger}");
+                        dropped.extend(lobby.pair(challenger, raw_id));
+                    }
+                }
+                C2sMessage::DeclineChallenge(challenger) => {
+                    if lobby.remove_challenge(challenger, raw_id) {
+                        dropped.push((challenger, raw_id));
+                    }
+                }
+                C2sMessage::PlayBot(difficulty) => {
+                    if lobby.is_paired(raw_id) {
+                        continue;
+                    }
+                    let bot_id =
+                        bots.spawn(&mut lobby, &mut game_message_events, difficulty, rooms.seed);
+                    info!("{raw_id} plays against bot {bot_id}");
+                    dropped.extend(lobby.pair(raw_id, bot_id));
+                }
+                C2sMessage::Watch(room_id) => {
+                    let room_id = RoomId(room_id);
+                    let Some(room) = rooms.get_mut(room_id) else {
+                        continue;
+                    };
+                    lobby.leave(raw_id);
+                    send_to_lobby(
+                        &mut game_message_events,
+                        &lobby,
+                        S2cMessage::LobbyLeft(raw_id),
+                    );
+                    info!("client {client_id} is spectating room {}", room_id.0);
+                    room.game_data.spectators.push(raw_id);
+                    Self::send_joined_room(*client_id, room_id, &mut game_message_events);
+                    rooms.join(raw_id, room_id);
                 }
             }
         }
+
+        dropped.extend(lobby.pair_queued());
+        for (challenger, challenged) in dropped {
+            send_to_clients(
+                &mut game_message_events,
+                [challenger, challenged],
+                S2cMessage::ChallengeDeclined(challenger, challenged),
+            );
+        }
+    }
+
+    /// Handles the rematch votes and leave requests of clients in a room.
+    fn room_system(
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        replicon_tick: Res<RepliconTick>,
+        mut lobby: ResMut<Lobby>,
+        mut rooms: ResMut<Rooms>,
+        mut client_messages: EventReader<FromClient<ClientMessage>>,
+        mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+        mut room_messages: EventWriter<RoomMessage>,
+        transport: Option<Res<NetcodeServerTransport>>,
+        host: Option<Res<HostPlayer>>,
+        players: Query<(&Player, &RoomId, &PlayerName)>,
+        mut paddles: Query<(&RoomId, &mut PlayerPosition), (With<Paddle>, Without<Ball>)>,
+        mut balls: Query<
+            (&RoomId, &mut PlayerPosition, &mut PlayerSpeed),
+            (With<Ball>, Without<Paddle>),
+        >,
+        mut matches: Query<(&RoomId, &mut Score, &mut Serve)>,
+    ) {
+        for FromClient { client_id, event } in client_messages.read() {
+            let raw_id = client_id.raw();
+            let Some(room_id) = rooms.room_of(raw_id) else {
+                continue;
+            };
+            let room = rooms.get_mut(room_id).expect("client room should exist");
+            let game_data = &mut room.game_data;
+            match event.msg {
+                C2sMessage::Rematch => {
+                    if game_data.phase != GameState::End
+                        || game_data.is_spectator(raw_id)
+                        || !game_data.left.is_empty()
+                        || game_data.rematch_votes.contains(&raw_id)
+                    {
+                        continue;
+                    }
+                    game_data.rematch_votes.push(raw_id);
+                    send_to_room(
+                        &mut game_message_events,
+                        &mut room_messages,
+                        room_id,
+                        game_data,
+                        S2cMessage::RematchVote(raw_id),
+                    );
+                    if game_data.rematch_votes.len() < 2 {
+                        continue;
+                    }
+
+                    info!("rematch in room {}", room_id.0);
+                    room.reseed();
+                    let game_data = &mut room.game_data;
+                    game_data.rematch_votes.clear();
+                    game_data.phase = GameState::Game;
+                    for (_, mut position) in paddles.iter_mut().filter(|(id, _)| **id == room_id) {
+                        position.y = 0.0;
+                    }
+                    for (_, mut position, mut velocity) in
+                        balls.iter_mut().filter(|(id, ..)| **id == room_id)
+                    {
+                        **position = Vec2::ZERO;
+                        let direction = if room.rng.gen() { 1.0 } else { -1.0 };
+                        **velocity = rules.serve_velocity(&mut room.rng, direction);
+                    }
+                    for (_, mut score, mut serve) in
+                        matches.iter_mut().filter(|(id, ..)| **id == room_id)
+                    {
+                        *score = Score::default();
+                        *serve = Serve::after(replicon_tick.get(), &rules, *tick_rate);
+                    }
+                    send_to_room(
+                        &mut game_message_events,
+                        &mut room_messages,
+                        room_id,
+                        game_data,
+                        S2cMessage::GameStart(game_data.actor1, game_data.actor2),
+                    );
+                }
+                C2sMessage::Leave => {
+                    if game_data.is_spectator(raw_id) {
+                        game_data.spectators.retain(|&id| id != raw_id);
+                    } else if game_data.phase == GameState::End {
+                        Self::free_slot(game_data, raw_id);
+                        send_to_room(
+                            &mut game_message_events,
+                            &mut room_messages,
+                            room_id,
+                            game_data,
+                            S2cMessage::PlayerLeft(raw_id),
+                        );
+                    } else {
+                        continue;
+                    }
+
+                    info!("client {client_id} left room {}", room_id.0);
+                    rooms.leave(raw_id);
+                    let name = players
+                        .iter()
+                        .find(|(player, player_room_id, _)| {
+                            player.0 == *client_id && **player_room_id == room_id
+                        })
+                        .map(|(.., name)| name.0.clone())
+                        .or_else(|| {
+                            host.as_ref()
+                                .filter(|_| *client_id == SERVER_ID)
+                                .map(|host| host.name.clone())
+                        })
+                        .unwrap_or_else(|| Self::client_name(transport.as_deref(), *client_id));
+                    Self::enter_lobby(&mut lobby, &mut game_message_events, raw_id, name);
+                }
+                C2sMessage::None
+                | C2sMessage::SetReady(_)
+                | C2sMessage::Challenge(_)
+                | C2sMessage::AcceptChallenge(_)
+                | C2sMessage::DeclineChallenge(_)
+                | C2sMessage::PlayBot(_)
+                | C2sMessage::Watch(_) => {}
+            }
+        }
+    }
+
+    /// Starts the matches of paired lobby members while rooms are free.
+    fn matchmaking_system(
+        mut commands: Commands,
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        replicon_tick: Res<RepliconTick>,
+        mut lobby: ResMut<Lobby>,
+        mut rooms: ResMut<Rooms>,
+        mut game_message_events: EventWriter<ToClients<ServerMessage>>,
+        mut room_messages: EventWriter<RoomMessage>,
+    ) {
+        while !rooms.is_full() {
+            let Some((actor1, actor2, name1, name2)) = lobby.next_pair() else {
+                break;
+            };
+            for client_id in [actor1, actor2] {
+                send_to_lobby(
+                    &mut game_message_events,
+                    &lobby,
+                    S2cMessage::LobbyLeft(client_id),
+                );
+            }
+
+            let serve = Serve::after(replicon_tick.get(), &rules, *tick_rate);
+            let room_id = rooms.open(&mut commands, &rules, serve);
+            let lobby_match = LobbyMatch {
+                actor1,
+                actor2,
+                name1: name1.clone(),
+                name2: name2.clone(),
+            };
+            for (client_id, name, x) in [
+                (actor1, name1, -rules.paddle_x()),
+                (actor2, name2, rules.paddle_x()),
+            ] {
+                commands.spawn(PlayerBundle::new(
+                    ClientId::from_raw(client_id),
+                    room_id,
+                    name,
+                    Vec2::new(x, 0.0),
+                    player_color(client_id),
+                ));
+                rooms.join(client_id, room_id);
+                Self::send_joined_room(
+                    ClientId::from_raw(client_id),
+                    room_id,
+                    &mut game_message_events,
+                );
+            }
+
+            let game_data = &mut rooms
+                .get_mut(room_id)
+                .expect("opened room should exist")
+                .game_data;
+            game_data.actor1 = actor1;
+            game_data.actor2 = actor2;
+            game_data.player_count = 2;
+            game_data.phase = GameState::Game;
+            send_to_room(
+                &mut game_message_events,
+                &mut room_messages,
+                room_id,
+                game_data,
+                S2cMessage::GameStart(actor1, actor2),
+            );
+            info!("game start in room {}", room_id.0);
+
+            send_to_lobby(
+                &mut game_message_events,
+                &lobby,
+                S2cMessage::MatchOpened(room_id.0, lobby_match.clone()),
+            );
+            lobby.open_match(room_id, lobby_match);
+        }
+    }
+
+    /// Takes a player out of a finished match.
+    ///
+    /// They stay an actor of the match, so the final score keeps their name, and their paddle
+    /// stays on the board until the room is closed.
+    fn free_slot(game_data: &mut GameData, client_id: u64) {
+        game_data.rematch_votes.retain(|&id| id != client_id);
+        if !game_data.left.contains(&client_id) {
+            game_data.left.push(client_id);
+        }
+        game_data.player_count -= 1;
+    }
+
+    /// Copies the state of every room onto its match entity for replication.
+    fn publish_match_state_system(rooms: Res<Rooms>, mut matches: Query<(&RoomId, &mut GameData)>) {
+        for (room_id, mut game_data) in &mut matches {
+            if let Some(room) = rooms.get(*room_id) {
+                game_data.set_if_neq(room.game_data.clone());
+            }
+        }
+    }
+}
+
+/// The game client, follows the replicated matches and draws the current one.
+///
+/// The menus are only shown when the app has an egui context, see `bevy_egui::EguiPlugin`, and
+/// once the [`LocalData`] is inserted.
+pub struct PingPongClientPlugin;
+
+impl Plugin for PingPongClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .init_resource::<GameData>()
+            .init_resource::<CurrentRoom>()
+            .init_resource::<InputHistory>()
+            .init_resource::<InterpolationSettings>()
+            .init_resource::<ServerClock>()
+            .init_resource::<PaddleKeys>()
+            .add_systems(Startup, Self::init_system)
+            .add_systems(
+                FixedUpdate,
+                Self::input_system.run_if(not(in_state(GameState::Menu))),
+            )
+            .add_systems(
+                Update,
+                (
+                    Self::sync_tick_rate_system
+                        .before(PingPongSharedPlugin::apply_tick_rate_system),
+                    Self::client_event_system,
+                    (Self::sync_rules_system, Self::fit_camera_system).chain(),
+                    Self::sync_match_state_system.after(Self::client_event_system),
+                    (
+                        // The host sees the authoritative positions and needs neither.
+                        (
+                            Self::reconcile_system,
+                            Self::record_snapshots_system,
+                            Self::interpolate_system,
+                        )
+                            .chain()
+                            .run_if(not(has_authority())),
+                        Self::draw_boxes_system,
+                    )
+                        .chain()
+                        .after(Self::sync_match_state_system)
+                        .run_if(not(in_state(GameState::Menu))),
+                    (
+                        Self::render_lobby_client,
+                        Self::render_gui_client,
+                        Self::render_end_client,
+                    )
+                        .after(Self::sync_match_state_system)
+                        .run_if(any_with_component::<EguiContext>())
+                        .run_if(resource_exists::<LocalData>()),
+                ),
+            );
+    }
+}
+
+impl PingPongClientPlugin {
+    fn init_system(mut commands: Commands) {
+        commands.spawn(Camera2dBundle {
+            camera: Camera {
+                order: -1,
+                ..default()
+            },
+            ..default()
+        });
+        commands.insert_resource(SplashTimer(Timer::from_seconds(
+            1.0 / 60.0,
+            TimerMode::Repeating,
+        )));
+    }
+
+    /// Copies the rules replicated by the server into the local resource.
+    fn sync_rules_system(
+        mut rules: ResMut<MatchRules>,
+        replicated_rules: Query<&MatchRules, Changed<MatchRules>>,
+    ) {
+        if let Some(replicated_rules) = replicated_rules.iter().last() {
+            *rules = replicated_rules.clone();
+        }
+    }
+
+    /// Takes over the tick rate of the server.
+    fn sync_tick_rate_system(
+        mut tick_rate: ResMut<TickRate>,
+        ticks: Query<&SimulationTick, Changed<SimulationTick>>,
+    ) {
+        if let Some(tick) = ticks.iter().last() {
+            if tick.rate != tick_rate.0 {
+                tick_rate.0 = tick.rate;
+            }
+        }
+    }
+
+    /// Copies the replicated state of the current match and switches to its phase.
+    fn sync_match_state_system(
+        current_room: Res<CurrentRoom>,
+        state: Res<State<GameState>>,
+        mut next_state: ResMut<NextState<GameState>>,
+        mut game_data: ResMut<GameData>,
+        matches: Query<(&RoomId, &GameData)>,
+    ) {
+        if let Some((_, replicated)) = matches
+            .iter()
+            .find(|(room_id, _)| current_room.0 == Some(**room_id))
+        {
+            game_data.set_if_neq(replicated.clone());
+        }
+        if *state.get() != game_data.phase {
+            next_state.set(game_data.phase);
+        }
+    }
+
+    /// Scales the camera so the whole field stays visible.
+    fn fit_camera_system(
+        rules: Res<MatchRules>,
+        mut projections: Query<&mut OrthographicProjection>,
+    ) {
+        if !rules.is_changed() {
+            return;
+        }
+        for mut projection in &mut projections {
+            projection.scaling_mode = ScalingMode::AutoMin {
+                min_width: rules.field_size.x,
+                min_height: rules.field_size.y,
+            };
+        }
+    }
+
+    fn client_event_system(
+        mut move_events: EventReader<ServerMessage>,
+        mut game_data: ResMut<GameData>,
+        mut current_room: ResMut<CurrentRoom>,
+        mut lobby: ResMut<Lobby>,
+        mut history: ResMut<InputHistory>,
+    ) {
+        for event in move_events.read() {
+            match event.msg {
+                S2cMessage::None => {}
+                S2cMessage::JoinedRoom(room_id) => {
+                    info!("joined room {room_id}");
+                    current_room.0 = Some(RoomId(room_id));
+                    *game_data = GameData::default();
+                    history.clear();
+                }
+                S2cMessage::EnteredLobby => {
+                    current_room.0 = None;
+                    *game_data = GameData::default();
+                    lobby.clear();
+                }
+                S2cMessage::LobbyJoined(client_id, ref name) => {
+                    lobby.enter(client_id, name.clone())
+                }
+                S2cMessage::LobbyReady(client_id, ready) => lobby.set_ready(client_id, ready),
+                S2cMessage::LobbyLeft(client_id) => lobby.leave(client_id),
+                S2cMessage::Challenge(challenger, challenged) => {
+                    lobby.add_challenge(challenger, challenged)
+                }
+                S2cMessage::ChallengeDeclined(challenger, challenged) => {
+                    lobby.remove_challenge(challenger, challenged);
+                }
+                S2cMessage::MatchOpened(room_id, ref lobby_match) => {
+                    lobby.open_match(RoomId(room_id), lobby_match.clone())
+                }
+                S2cMessage::MatchClosed(room_id) => lobby.close_match(RoomId(room_id)),
+                // The match state itself is replicated, these only notify about changes.
+                S2cMessage::GameStart(actor1_id, actor2_id) => {
+                    info!("match between {actor1_id} and {actor2_id} started")
+                }
+                S2cMessage::RoundResult(client_actor_id) => {
+                    info!("point for player {client_actor_id}");
+                }
+                S2cMessage::GameEnd => info!("match over"),
+                S2cMessage::PlayerDisconnected(client_id) => {
+                    info!("player {client_id} lost their connection")
+                }
+                S2cMessage::PlayerReconnected(client_id) => {
+                    info!("player {client_id} reconnected")
+                }
+                S2cMessage::Forfeit(winner_id) => info!("player {winner_id} wins by forfeit"),
+                S2cMessage::RematchVote(client_id) => info!("player {client_id} wants a rematch"),
+                S2cMessage::PlayerLeft(client_id) => info!("player {client_id} left the match"),
+            }
+        }
     }
 
-    fn notify_game_state() {}
     fn draw_boxes_system(
-        mut egui_ctx: Query<&mut EguiContext>,
+        rules: Res<MatchRules>,
+        current_room: Res<CurrentRoom>,
         mut gizmos: Gizmos,
-        players: Query<(&PlayerPosition, &PlayerColor), With<Paddle>>,
-        ball: Query<(&PlayerPosition, &PlayerColor), With<Ball>>,
+        players: Query<
+            (
+                &RoomId,
+                &PlayerPosition,
+                Option<&PredictedPosition>,
+                Option<&InterpolatedPosition>,
+                &PlayerColor,
+            ),
+            With<Paddle>,
+        >,
+        ball: Query<
+            (
+                &RoomId,
+                &PlayerPosition,
+                Option<&InterpolatedPosition>,
+                &PlayerColor,
+            ),
+            With<Ball>,
+        >,
     ) {
-        for (position, color) in &players {
+        let in_room = |room_id: &RoomId| current_room.0 == Some(*room_id);
+        for (_, position, predicted, interpolated, color) in
+            players.iter().filter(|(room_id, ..)| in_room(room_id))
+        {
+            // The local paddle is predicted, remote ones are interpolated.
+            let position = predicted
+                .map(|predicted| **predicted)
+                .or(interpolated.map(|interpolated| **interpolated))
+                .unwrap_or(**position);
             gizmos.rect(
                 Vec3::new(position.x, position.y, 0.0),
                 Quat::IDENTITY,
-                Vec2::new(PADDLE_WIDTH, PADDLE_HEIGHT),
+                rules.paddle_size,
                 color.0,
             );
         }
-        for (ball_pos, ball_color) in &ball {
+        for (_, ball_pos, interpolated, ball_color) in
+            ball.iter().filter(|(room_id, ..)| in_room(room_id))
+        {
+            let ball_pos = interpolated.map_or(**ball_pos, |interpolated| **interpolated);
             gizmos.rect(
                 Vec3::new(ball_pos.x, ball_pos.y, 0.0),
                 Quat::IDENTITY,
-                Vec2::new(BALL_WIDTH, BALL_WIDTH),
+                Vec2::splat(rules.ball_size),
                 ball_color.0,
             )
         }
     }
 
-    fn input_system(mut move_events: EventWriter<MoveDirection>, input: Res<Input<KeyCode>>) {
+    /// Rebuilds the predicted position of the local paddle on every authoritative update.
+    fn reconcile_system(
+        mut commands: Commands,
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        local_data: Res<LocalData>,
+        current_room: Res<CurrentRoom>,
+        mut history: ResMut<InputHistory>,
+        mut paddles: Query<
+            (
+                Entity,
+                &Player,
+                &RoomId,
+                &PlayerPosition,
+                &InputAck,
+                Option<&mut PredictedPosition>,
+            ),
+            Or<(Changed<PlayerPosition>, Changed<InputAck>)>,
+        >,
+    ) {
+        for (entity, player, room_id, position, ack, predicted) in &mut paddles {
+            if player.0.raw() != local_data.client_id || current_room.0 != Some(*room_id) {
+                continue;
+            }
+            history.acknowledge(ack.0);
+            let position = history.replay(**position, &rules, tick_rate.duration());
+            match predicted {
+                Some(mut predicted) => **predicted = position,
+                None => {
+                    commands.entity(entity).insert(PredictedPosition(position));
+                }
+            }
+        }
+    }
+
+    /// Records a snapshot of the current room whenever a server tick arrived.
+    fn record_snapshots_system(
+        mut commands: Commands,
+        time: Res<Time>,
+        current_room: Res<CurrentRoom>,
+        mut clock: ResMut<ServerClock>,
+        ticks: Query<&SimulationTick, Changed<SimulationTick>>,
+        mut entities: Query<(
+            Entity,
+            &RoomId,
+            Ref<PlayerPosition>,
+            Option<&mut SnapshotBuffer>,
+        )>,
+    ) {
+        let Some(tick) = ticks.iter().last() else {
+            return;
+        };
+        let server_time = tick.seconds();
+        clock.observe(server_time, time.elapsed_seconds_f64());
+
+        for (entity, room_id, position, buffer) in &mut entities {
+            if current_room.0 != Some(*room_id) {
+                continue;
+            }
+            match buffer {
+                Some(mut buffer) => buffer.push(server_time, **position),
+                None => {
+                    let mut buffer = SnapshotBuffer::default();
+                    buffer.push(server_time, **position);
+                    commands.entity(entity).insert(buffer);
+                }
+            }
+        }
+    }
+
+    /// Moves remote entities to their position slightly in the past.
+    fn interpolate_system(
+        mut commands: Commands,
+        time: Res<Time>,
+        clock: Res<ServerClock>,
+        settings: Res<InterpolationSettings>,
+        mut entities: Query<(
+            Entity,
+            &mut SnapshotBuffer,
+            Option<&mut InterpolatedPosition>,
+        )>,
+    ) {
+        let Some(server_time) = clock.server_time(time.elapsed_seconds_f64()) else {
+            return;
+        };
+        let render_time = server_time - settings.delay as f64;
+        for (entity, mut buffer, interpolated) in &mut entities {
+            let Some(position) = buffer.sample(render_time, &settings) else {
+                continue;
+            };
+            match interpolated {
+                Some(mut interpolated) => **interpolated = position,
+                None => {
+                    commands
+                        .entity(entity)
+                        .insert(InterpolatedPosition(position));
+                }
+            }
+        }
+    }
+
+    /// Samples the held keys once per simulation tick.
+    fn input_system(
+        mut move_events: EventWriter<MoveDirection>,
+        input: Res<Input<KeyCode>>,
+        keys: Res<PaddleKeys>,
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        game_data: Res<GameData>,
+        local_data: Res<LocalData>,
+        current_room: Res<CurrentRoom>,
+        mut history: ResMut<InputHistory>,
+        mut predicted: Query<(&RoomId, &mut PredictedPosition)>,
+    ) {
+        if game_data.phase != GameState::Game || game_data.is_spectator(local_data.client_id) {
+            return;
+        }
+
         let mut direction = Vec2::ZERO;
         // if input.pressed(KeyCode::Right) {
         //     direction.x += 1.0;
@@ -337,27 +1427,257 @@ impl PingPongPlugin {
         // if input.pressed(KeyCode::Left) {
         //     direction.x -= 1.0;
         // }
-        if input.pressed(KeyCode::Up) {
+        if input.pressed(keys.up) {
             direction.y += 1.0;
         }
-        if input.pressed(KeyCode::Down) {
+        if input.pressed(keys.down) {
             direction.y -= 1.0;
         }
         if direction != Vec2::ZERO {
-            move_events.send(MoveDirection(direction.normalize_or_zero()));
+            let direction = direction.normalize_or_zero();
+            let sequence = history.record(direction);
+            move_events.send(MoveDirection {
+                direction,
+                sequence,
+            });
+            for (_, mut position) in predicted
+                .iter_mut()
+                .filter(|(room_id, _)| current_room.0 == Some(**room_id))
+            {
+                **position = rules.move_paddle(**position, direction, tick_rate.duration());
+            }
+        }
+    }
+
+    /// Shows the lobby while the client waits for a match.
+    pub fn render_lobby_client(
+        mut egui_ctx: Query<&mut EguiContext>,
+        current_room: Res<CurrentRoom>,
+        lobby: Res<Lobby>,
+        local_data: Res<LocalData>,
+        hot_seat: Option<Res<HotSeat>>,
+        mut lobby_events: EventWriter<ClientMessage>,
+    ) {
+        if current_room.0.is_some() {
+            return;
+        }
+
+        let client_id = local_data.client_id;
+        let name_of = |id: u64| {
+            lobby
+                .member(id)
+                .map_or_else(|| format!("Client: {id}"), |member| member.name.clone())
+        };
+        let mut requests = Vec::new();
+        egui::Window::new("Lobby")
+            .anchor(Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .collapsible(false)
+            .resizable(false)
+            .show(egui_ctx.single_mut().get_mut(), |ui| {
+                let Some(me) = lobby.member(client_id) else {
+                    ui.label("Connecting...");
+                    return;
+                };
+
+                if hot_seat.is_some() {
+                    if me.ready {
+                        ui.label("Starting the match...");
+                    } else if ui.button("Start match").clicked() {
+                        requests.push(C2sMessage::SetReady(true));
+                    }
+                    return;
+                }
+
+                let mut ready = me.ready;
+                if ui.checkbox(&mut ready, "Ready for a match").changed() {
+                    requests.push(C2sMessage::SetReady(ready));
+                }
+                if me.ready {
+                    ui.label("Searching for an opponent...");
+                } else if !lobby.is_paired(client_id) {
+                    ui.horizontal(|ui| {
+                        ui.label("Play against a bot:");
+                        for difficulty in BotDifficulty::ALL {
+                            if ui.button(difficulty.to_string()).clicked() {
+                                requests.push(C2sMessage::PlayBot(difficulty));
+                            }
+                        }
+                    });
+                }
+                for challenger in lobby.challengers_of(client_id) {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} challenges you", name_of(challenger)));
+                        if ui.button("Accept").clicked() {
+                            requests.push(C2sMessage::AcceptChallenge(challenger));
+                        }
+                        if ui.button("Decline").clicked() {
+                            requests.push(C2sMessage::DeclineChallenge(challenger));
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.heading("Players");
+                egui::Grid::new("lobby_players")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (member_id, member) in lobby.members() {
+                            if member_id == client_id {
+                                ui.label(format!("{} (you)", member.name));
+                            } else {
+                                ui.label(&member.name);
+                            }
+                            ui.label(if member.ready { "ready" } else { "" });
+                            if member_id == client_id {
+                                ui.label("");
+                            } else if lobby.has_challenge(client_id, member_id) {
+                                ui.label("challenged");
+                            } else if ui.button("Challenge").clicked() {
+                                requests.push(C2sMessage::Challenge(member_id));
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                if lobby.matches().next().is_some() {
+                    ui.separator();
+                    ui.heading("Matches");
+                    egui::Grid::new("lobby_matches")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (room_id, lobby_match) in lobby.matches() {
+                                ui.label(format!("{} vs {}", lobby_match.name1, lobby_match.name2));
+                                if ui.button("Watch").clicked() {
+                                    requests.push(C2sMessage::Watch(room_id.0));
+                                }
+                                ui.end_row();
+                            }
+                        });
+                }
+            });
+
+        for msg in requests {
+            lobby_events.send(ClientMessage { msg });
+        }
+    }
+
+    /// Shows the final score once the match is over and lets players vote for a rematch.
+    pub fn render_end_client(
+        mut egui_ctx: Query<&mut EguiContext>,
+        current_room: Res<CurrentRoom>,
+        game_data: Res<GameData>,
+        local_data: Res<LocalData>,
+        players: Query<(&Player, &PlayerName)>,
+        matches: Query<(&RoomId, &Score)>,
+        mut room_events: EventWriter<ClientMessage>,
+    ) {
+        if current_room.0.is_none() || game_data.phase != GameState::End {
+            return;
+        }
+        let score = matches
+            .iter()
+            .find(|(room_id, _)| current_room.0 == Some(**room_id))
+            .map(|(_, score)| *score)
+            .unwrap_or_default();
+
+        let client_id = local_data.client_id;
+        let name_of = |id: u64| {
+            players
+                .iter()
+                .find(|(player, _)| player.0.raw() == id)
+                .map_or_else(|| format!("Client: {id}"), |(_, name)| name.0.clone())
+        };
+        let mut requests = Vec::new();
+        egui::Window::new("Match over")
+            .anchor(Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .collapsible(false)
+            .resizable(false)
+            .show(egui_ctx.single_mut().get_mut(), |ui| {
+                ui.vertical_centered(|ui| {
+                    match game_data.winner(&score) {
+                        Some(winner_id) if game_data.forfeit_winner.is_some() => {
+                            ui.heading(format!("{} wins by forfeit", name_of(winner_id)))
+                        }
+                        Some(winner_id) => ui.heading(format!("{} wins", name_of(winner_id))),
+                        None => ui.heading("Draw"),
+                    };
+                    ui.label(format!(
+                        "{} {} : {} {}",
+                        name_of(game_data.actor1),
+                        score.left,
+                        score.right,
+                        name_of(game_data.actor2)
+                    ));
+                    for &left_id in &game_data.left {
+                        ui.label(format!("{} left the match", name_of(left_id)));
+                    }
+
+                    ui.separator();
+                    if !game_data.is_spectator(client_id) {
+                        let opponent_id = if client_id == game_data.actor1 {
+                            game_data.actor2
+                        } else {
+                            game_data.actor1
+                        };
+                        if game_data.left.contains(&opponent_id) {
+                            // Nobody left to play the rematch against.
+                        } else if game_data.rematch_votes.contains(&client_id) {
+                            ui.label(format!(
+                                "Waiting for {} to accept the rematch...",
+                                name_of(opponent_id)
+                            ));
+                        } else {
+                            if game_data.rematch_votes.contains(&opponent_id) {
+                                ui.label(format!("{} wants a rematch", name_of(opponent_id)));
+                            }
+                            if ui.button("Rematch").clicked() {
+                                requests.push(C2sMessage::Rematch);
+                            }
+                        }
+                    }
+                    if ui.button("Back to lobby").clicked() {
+                        requests.push(C2sMessage::Leave);
+                    }
+                });
+            });
+
+        for msg in requests {
+            room_events.send(ClientMessage { msg });
         }
     }
 
     pub fn render_gui_client(
         mut egui_ctx: Query<&mut EguiContext>,
+        current_room: Res<CurrentRoom>,
         game_data: Res<GameData>,
         local_data: Res<LocalData>,
+        rules: Res<MatchRules>,
+        tick_rate: Res<TickRate>,
+        players: Query<(&Player, &PlayerName)>,
+        matches: Query<(&RoomId, &Score, &Serve)>,
+        ticks: Query<&SimulationTick>,
+        mut room_events: EventWriter<ClientMessage>,
     ) {
+        if current_room.0.is_none() {
+            return;
+        }
+
+        let (score, serve) = matches
+            .iter()
+            .find(|(room_id, ..)| current_room.0 == Some(**room_id))
+            .map(|(_, score, serve)| (*score, *serve))
+            .unwrap_or_default();
+        let countdown = ticks
+            .iter()
+            .next()
+            .filter(|_| game_data.phase == GameState::Game)
+            .and_then(|tick| serve.countdown(tick.tick, *tick_rate));
+
         let client_id = local_data.client_id;
         let my_score = if client_id == game_data.actor1 {
-            game_data.score1
+            score.left
         } else {
-            game_data.score2
+            score.right
         };
         let opponent_id = if client_id == game_data.actor1 {
             game_data.actor2
@@ -366,15 +1686,49 @@ impl PingPongPlugin {
         };
 
         let opponent_score = if client_id == game_data.actor1 {
-            game_data.score2
+            score.right
+        } else {
+            score.left
+        };
+        let name_of = |id: u64| {
+            players
+                .iter()
+                .find(|(player, _)| player.0.raw() == id)
+                .map_or_else(|| format!("Client: {id}"), |(_, name)| name.0.clone())
+        };
+        // Spectators see the players on the side of their paddles.
+        let spectating = game_data.is_spectator(client_id);
+        let (left_id, left_score, right_id, right_score) = if spectating {
+            (game_data.actor1, score.left, game_data.actor2, score.right)
         } else {
-            game_data.score1
+            (client_id, my_score, opponent_id, opponent_score)
         };
+        let left_name = name_of(left_id);
+        let right_name = name_of(right_id);
         egui::CentralPanel::default().frame(Frame::none()).show(
-            &egui_ctx.single_mut().get_mut(),
+            egui_ctx.single_mut().get_mut(),
             |ui| {
                 ui.horizontal_top(|ui| {
-                    ui.vertical_centered(|ui| ui.label(format!("Round: {}/{}", game_data.round,3)))
+                    ui.vertical_centered(|ui| {
+                        if spectating {
+                            ui.label("Spectating");
+                            if ui.button("Back to lobby").clicked() {
+                                room_events.send(ClientMessage {
+                                    msg: C2sMessage::Leave,
+                                });
+                            }
+                        }
+                        ui.label(rules.progress_label(score.round));
+                        if let Some(seconds) = countdown {
+                            ui.heading(format!("Serve in {seconds}"));
+                        }
+                        for &absent_id in &game_data.absent {
+                            ui.label(format!(
+                                "Waiting for {} to reconnect...",
+                                name_of(absent_id)
+                            ));
+                        }
+                    })
                 });
 
                 ui.put(
@@ -383,9 +1737,8 @@ impl PingPongPlugin {
                         max: Pos2 { x: 250.0, y: 100.0 },
                     },
                     |ui: &mut Ui| {
-                        ui.horizontal_top(|ui| ui.label(format!("Client: {client_id}")))
-                            .response;
-                        ui.horizontal_top(|ui| ui.label(format!("Score: {my_score}")))
+                        ui.horizontal_top(|ui| ui.label(&left_name));
+                        ui.horizontal_top(|ui| ui.label(format!("Score: {left_score}")))
                             .response
                     },
                 );
@@ -399,66 +1752,43 @@ impl PingPongPlugin {
                         },
                     },
                     |ui: &mut Ui| {
-                        ui.horizontal_top(|ui| ui.label(format!("Client: {opponent_id}")))
-                            .response;
-                        ui.horizontal_top(|ui| ui.label(format!("Score: {opponent_score}")))
+                        ui.horizontal_top(|ui| ui.label(&right_name));
+                        ui.horizontal_top(|ui| ui.label(format!("Score: {right_score}")))
                             .response
                     },
                 );
             },
         );
-        // egui::CentralPanel::default().show(egui_ctx.single_mut().get_mut(), |ui| {
-        // ui.horizontal_top(|ui| {
-        //     ui.vertical_centered(|ui| ui.label(format!("Round: {}", game_data.round + 1)))
-        // });
-
-        // ui.put(
-        //     bevy_egui::egui::Rect {
-        //         min: Pos2 { x: 0.0, y: 0.0 },
-        //         max: Pos2 { x: 250.0, y: 100.0 },
-        //     },
-        //     |ui: &mut Ui| {
-        //         ui.horizontal_top(|ui| ui.label(format!("Client: {opponent_id}")))
-        //             .response;
-        //         ui.horizontal_top(|ui| ui.label(format!("Score: {opponent_score}")))
-        //             .response
-        //     },
-        // );
-
-        // ui.put(
-        //     bevy_egui::egui::Rect {
-        //         min: Pos2 { x: 1030.0, y: 0.0 },
-        //         max: Pos2 {
-        //             x: 1280.0,
-        //             y: 100.0,
-        //         },
-        //     },
-        //     |ui: &mut Ui| {
-        //         ui.horizontal_top(|ui| ui.label(format!("Client: {client_id}")))
-        //             .response;
-        //         ui.horizontal_top(|ui| ui.label(format!("Score: {my_score}")))
-        //             .response
-        //     },
-        // );
-        // });
     }
 }
 
 #[derive(Bundle)]
 pub struct PlayerBundle {
     player: Player,
+    room_id: RoomId,
+    name: PlayerName,
     position: PlayerPosition,
     color: PlayerColor,
+    input_ack: InputAck,
     replication: Replication,
     paddle: Paddle,
 }
 
 impl PlayerBundle {
-    pub fn new(client_id: ClientId, position: Vec2, color: Color) -> Self {
+    pub fn new(
+        client_id: ClientId,
+        room_id: RoomId,
+        name: String,
+        position: Vec2,
+        color: Color,
+    ) -> Self {
         Self {
             player: Player(client_id),
+            room_id,
+            name: PlayerName(name),
             position: PlayerPosition(position),
             color: PlayerColor(color),
+            input_ack: InputAck::default(),
             replication: Replication,
             paddle: Paddle {},
         }
@@ -467,6 +1797,7 @@ impl PlayerBundle {
 
 #[derive(Bundle)]
 pub struct BallBundle {
+    room_id: RoomId,
     position: PlayerPosition,
     speed: PlayerSpeed,
     color: PlayerColor,
@@ -475,10 +1806,11 @@ pub struct BallBundle {
 }
 
 impl BallBundle {
-    pub fn new(position: Vec2, color: Color) -> Self {
+    pub fn new(room_id: RoomId, position: Vec2, velocity: Vec2, color: Color) -> Self {
         Self {
+            room_id,
             position: PlayerPosition(position),
-            speed: PlayerSpeed(Vec2::new(SPEED, SPEED)),
+            speed: PlayerSpeed(velocity),
             color: PlayerColor(color),
             replication: Replication,
             ball: Ball {},
@@ -488,7 +1820,11 @@ impl BallBundle {
 
 /// Contains the client ID of the player.
 #[derive(Component, Serialize, Deserialize)]
-struct Player(ClientId);
+pub struct Player(ClientId);
+
+/// Display name the player connected with.
+#[derive(Component, Serialize, Deserialize)]
+pub struct PlayerName(String);
 
 #[derive(Component, Deserialize, Serialize, Deref, DerefMut)]
 struct PlayerPosition(Vec2);
@@ -500,8 +1836,12 @@ struct PlayerColor(Color);
 struct PlayerSpeed(Vec2);
 
 /// A movement event for the controlled box.
-#[derive(Debug, Default, Deserialize, Event, Serialize)]
-struct MoveDirection(Vec2);
+#[derive(Clone, Copy, Debug, Default, Deserialize, Event, Serialize)]
+struct MoveDirection {
+    direction: Vec2,
+    /// Increasing number the server acknowledges in the [`InputAck`] of the paddle.
+    sequence: u32,
+}
 
 #[derive(Component, Serialize, Deserialize)]
 struct Paddle;
@@ -513,14 +1853,64 @@ struct Ball;
 pub struct ServerMessage {
     msg: S2cMessage,
 }
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub enum S2cMessage {
     #[default]
     None,
+    /// The client was assigned to the room with the contained id.
+    JoinedRoom(u32),
+    /// The client is in the lobby, followed by its members and running matches.
+    EnteredLobby,
+    LobbyJoined(u64, String),
+    /// A lobby member changed their ready flag.
+    LobbyReady(u64, bool),
+    LobbyLeft(u64),
+    /// The first client challenges the second one.
+    Challenge(u64, u64),
+    /// A challenge was declined or dropped because one of the clients was paired.
+    ChallengeDeclined(u64, u64),
+    /// A match started in the room with the contained id.
+    MatchOpened(u32, LobbyMatch),
+    MatchClosed(u32),
+    /// A player voted for a rematch, it starts with a GameStart once both did.
+    RematchVote(u64),
+    /// A player left the finished match.
+    PlayerLeft(u64),
     GameStart(u64, u64),
-    ClientJoin(u64, i32),
+    /// The contained player scored a point, 1 for the left and 2 for the right one.
     RoundResult(u64),
     GameEnd,
+    /// A player lost their connection, the match is paused until they rejoin.
+    PlayerDisconnected(u64),
+    PlayerReconnected(u64),
+    /// A player didn't rejoin in time, the match is won by the contained player.
+    Forfeit(u64),
+}
+
+/// A lobby request of a client.
+#[derive(Debug, Default, Deserialize, Event, Serialize)]
+pub struct ClientMessage {
+    pub msg: C2sMessage,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub enum C2sMessage {
+    #[default]
+    None,
+    /// Enter or leave the matchmaking queue.
+    SetReady(bool),
+    /// Challenge the lobby member with the contained id.
+    Challenge(u64),
+    AcceptChallenge(u64),
+    DeclineChallenge(u64),
+    /// Play a match against a bot of the contained difficulty.
+    PlayBot(BotDifficulty),
+    /// Spectate the match in the room with the contained id.
+    Watch(u32),
+    /// Vote for a rematch once the match is over.
+    Rematch,
+    /// Go back to the lobby after the match or stop spectating.
+    Leave,
 }
 
 #[derive(Resource, Deref, DerefMut)]
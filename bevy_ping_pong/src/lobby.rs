@@ -0,0 +1,237 @@
+//! The lobby holds connected clients until they are paired for a match.
+//!
+//! Clients that mark themselves ready enter a first come, first served matchmaking queue.
+//! Alternatively a client challenges another one, who can accept or decline. Paired clients
+//! wait in the lobby until a room is free for their match.
+//!
+//! The server keeps the authoritative [`Lobby`] and sends every change to the clients in it,
+//! which mirror it into their own [`Lobby`] resource to draw the lobby screen.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{room::RoomId, send_to_clients, S2cMessage, ServerMessage};
+
+pub struct LobbyMember {
+    pub name: String,
+    /// The member waits in the matchmaking queue.
+    pub ready: bool,
+}
+
+/// A running match listed in the lobby, with the names of its players.
+///
+/// Clients only see the paddles of their own room, so the names come along with the match.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LobbyMatch {
+    pub actor1: u64,
+    pub actor2: u64,
+    pub name1: String,
+    pub name2: String,
+}
+
+#[derive(Resource, Default)]
+pub struct Lobby {
+    members: BTreeMap<u64, LobbyMember>,
+    /// Open challenges as challenger and challenged client.
+    challenges: Vec<(u64, u64)>,
+    /// Matches that can be watched.
+    matches: BTreeMap<RoomId, LobbyMatch>,
+    /// Ready members in the order they became ready.
+    queue: VecDeque<u64>,
+    /// Paired members waiting for a free room.
+    pairs: VecDeque<(u64, u64)>,
+}
+
+impl Lobby {
+    pub fn members(&self) -> impl Iterator<Item = (u64, &LobbyMember)> {
+        self.members
+            .iter()
+            .map(|(&client_id, member)| (client_id, member))
+    }
+
+    pub fn member(&self, client_id: u64) -> Option<&LobbyMember> {
+        self.members.get(&client_id)
+    }
+
+    pub fn contains(&self, client_id: u64) -> bool {
+        self.members.contains_key(&client_id)
+    }
+
+    pub fn matches(&self) -> impl Iterator<Item = (RoomId, &LobbyMatch)> {
+        self.matches
+            .iter()
+            .map(|(&room_id, lobby_match)| (room_id, lobby_match))
+    }
+
+    /// Returns whether `challenger` challenged `challenged` and is waiting for an answer.
+    pub fn has_challenge(&self, challenger: u64, challenged: u64) -> bool {
+        self.challenges.contains(&(challenger, challenged))
+    }
+
+    /// Clients waiting for the answer of `client_id` to their challenge.
+    pub fn challengers_of(&self, client_id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.challenges
+            .iter()
+            .filter(move |&&(_, challenged)| challenged == client_id)
+            .map(|&(challenger, _)| challenger)
+    }
+
+    /// Ready members that aren't paired yet, in the order they became ready.
+    pub fn queued(&self) -> impl Iterator<Item = u64> + '_ {
+        self.queue.iter().copied()
+    }
+
+    /// Returns whether the member was paired and waits for a free room.
+    pub fn is_paired(&self, client_id: u64) -> bool {
+        self.pairs
+            .iter()
+            .any(|&(a, b)| a == client_id || b == client_id)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn enter(&mut self, client_id: u64, name: String) {
+        self.members
+            .insert(client_id, LobbyMember { name, ready: false });
+    }
+
+    /// Removes a member together with their challenges and queue entries.
+    ///
+    /// If the member was already paired, the partner is put back in front of the queue when they
+    /// were ready.
+    pub(crate) fn leave(&mut self, client_id: u64) {
+        self.members.remove(&client_id);
+        self.queue.retain(|&id| id != client_id);
+        self.challenges
+            .retain(|&(challenger, challenged)| challenger != client_id && challenged != client_id);
+
+        let Some(index) = self
+            .pairs
+            .iter()
+            .position(|&(a, b)| a == client_id || b == client_id)
+        else {
+            return;
+        };
+        let (a, b) = self.pairs.remove(index).unwrap();
+        let partner = if a == client_id { b } else { a };
+        if self
+            .members
+            .get(&partner)
+            .is_some_and(|member| member.ready)
+        {
+            self.queue.push_front(partner);
+        }
+    }
+
+    /// Updates the ready flag of a member and its place in the matchmaking queue.
+    pub(crate) fn set_ready(&mut self, client_id: u64, ready: bool) {
+        let Some(member) = self.members.get_mut(&client_id) else {
+            return;
+        };
+        member.ready = ready;
+        if self
+            .pairs
+            .iter()
+            .any(|&(a, b)| a == client_id || b == client_id)
+        {
+            return;
+        }
+        self.queue.retain(|&id| id != client_id);
+        if ready {
+            self.queue.push_back(client_id);
+        }
+    }
+
+    pub(crate) fn add_challenge(&mut self, challenger: u64, challenged: u64) {
+        if !self.has_challenge(challenger, challenged) {
+            self.challenges.push((challenger, challenged));
+        }
+    }
+
+    /// Removes a challenge and returns whether it was open.
+    pub(crate) fn remove_challenge(&mut self, challenger: u64, challenged: u64) -> bool {
+        let len = self.challenges.len();
+        self.challenges
+            .retain(|&challenge| challenge != (challenger, challenged));
+        self.challenges.len() != len
+    }
+
+    /// Pairs two members, taking them out of the queue.
+    ///
+    /// Returns the other challenges of both members, which are dropped.
+    pub(crate) fn pair(&mut self, actor1: u64, actor2: u64) -> Vec<(u64, u64)> {
+        self.queue.retain(|&id| id != actor1 && id != actor2);
+        let (dropped, challenges) =
+            self.challenges
+                .drain(..)
+                .partition(|&(challenger, challenged)| {
+                    [actor1, actor2].contains(&challenger) || [actor1, actor2].contains(&challenged)
+                });
+        self.challenges = challenges;
+        self.pairs.push_back((actor1, actor2));
+
+        dropped
+    }
+
+    /// Pairs the members of the queue in the order they became ready.
+    ///
+    /// Returns the challenges dropped by pairing them.
+    pub(crate) fn pair_queued(&mut self) -> Vec<(u64, u64)> {
+        let mut dropped = Vec::new();
+        while self.queue.len() >= 2 {
+            let actor1 = self.queue.pop_front().unwrap();
+            let actor2 = self.queue.pop_front().unwrap();
+            info!("paired {actor1} and {actor2} from the queue");
+            dropped.extend(self.pair(actor1, actor2));
+        }
+
+        dropped
+    }
+
+    /// Takes the longest waiting pair out of the lobby to start their match.
+    pub(crate) fn next_pair(&mut self) -> Option<(u64, u64, String, String)> {
+        let (actor1, actor2) = self.pairs.pop_front()?;
+        let name1 = self.members.remove(&actor1)?.name;
+        let name2 = self.members.remove(&actor2)?.name;
+
+        Some((actor1, actor2, name1, name2))
+    }
+
+    pub(crate) fn open_match(&mut self, room_id: RoomId, lobby_match: LobbyMatch) {
+        self.matches.insert(room_id, lobby_match);
+    }
+
+    pub(crate) fn close_match(&mut self, room_id: RoomId) {
+        self.matches.remove(&room_id);
+    }
+
+    /// Messages that bring a client which enters the lobby up to date.
+    pub(crate) fn state_messages(&self) -> Vec<S2cMessage> {
+        let mut messages = vec![S2cMessage::EnteredLobby];
+        for (&client_id, member) in &self.members {
+            messages.push(S2cMessage::LobbyJoined(client_id, member.name.clone()));
+            if member.ready {
+                messages.push(S2cMessage::LobbyReady(client_id, true));
+            }
+        }
+        for (&room_id, lobby_match) in &self.matches {
+            messages.push(S2cMessage::MatchOpened(room_id.0, lobby_match.clone()));
+        }
+
+        messages
+    }
+}
+
+/// Sends a message to every client in the lobby.
+pub(crate) fn send_to_lobby(
+    game_message_events: &mut EventWriter<ToClients<ServerMessage>>,
+    lobby: &Lobby,
+    msg: S2cMessage,
+) {
+    send_to_clients(game_message_events, lobby.members.keys().copied(), msg);
+}
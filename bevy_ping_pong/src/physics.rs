@@ -0,0 +1,314 @@
+//! Continuous collision detection for the ball.
+//!
+//! Instead of testing for overlaps after moving, [`step_ball`] sweeps the ball along its
+//! path through the tick and computes the time of impact with the walls and paddles. The ball is
+//! moved exactly to the contact, reflected and continues with the rest of the tick, so fast balls
+//! can't tunnel through a paddle. A contact only reflects the ball while it moves into the
+//! obstacle, so a ball that still touches a paddle after bouncing off isn't reflected again.
+
+use bevy::prelude::*;
+
+/// Contacts resolved within a tick at most, the rest of the tick is dropped after that.
+const MAX_CONTACTS: usize = 4;
+
+/// An axis-aligned box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub center: Vec2,
+    pub half_size: Vec2,
+}
+
+impl Aabb {
+    pub fn new(center: Vec2, size: Vec2) -> Self {
+        Self {
+            center,
+            half_size: size / 2.0,
+        }
+    }
+}
+
+/// The first point where a moving box touches an obstacle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// Fraction of the movement until the contact, between 0 and 1.
+    pub time: f32,
+    /// Surface normal of the obstacle at the contact.
+    pub normal: Vec2,
+}
+
+/// Sweeps a box moving by `displacement` against an obstacle.
+///
+/// A box that already overlaps the obstacle touches it immediately, with the normal pointing
+/// horizontally away from the obstacle's center.
+pub fn sweep(moving: Aabb, displacement: Vec2, obstacle: Aabb) -> Option<Contact> {
+    // Shrinking the moving box to a point and growing the obstacle by its size turns this into
+    // a ray cast.
+    let min = obstacle.center - obstacle.half_size - moving.half_size;
+    let max = obstacle.center + obstacle.half_size + moving.half_size;
+    let start = moving.center;
+
+    let inside = start.cmpgt(min).all() && start.cmplt(max).all();
+    if inside {
+        let side = if start.x < obstacle.center.x {
+            -1.0
+        } else {
+            1.0
+        };
+        return Some(Contact {
+            time: 0.0,
+            normal: Vec2::new(side, 0.0),
+        });
+    }
+
+    let mut entry = Vec2::splat(f32::NEG_INFINITY);
+    let mut exit = Vec2::splat(f32::INFINITY);
+    for axis in 0..2 {
+        if displacement[axis] == 0.0 {
+            if start[axis] <= min[axis] || start[axis] >= max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let to_min = (min[axis] - start[axis]) / displacement[axis];
+        let to_max = (max[axis] - start[axis]) / displacement[axis];
+        entry[axis] = to_min.min(to_max);
+        exit[axis] = to_min.max(to_max);
+    }
+
+    let time = entry.max_element();
+    if time > exit.min_element() || !(0.0..=1.0).contains(&time) {
+        return None;
+    }
+    let normal = if entry.x >= entry.y {
+        Vec2::new(-displacement.x.signum(), 0.0)
+    } else {
+        Vec2::new(0.0, -displacement.y.signum())
+    };
+
+    Some(Contact { time, normal })
+}
+
+/// Returns when a ball moving by `displacement` reaches the top or bottom wall, if it does.
+///
+/// `max_y` is the highest position of the ball center.
+fn sweep_walls(position: Vec2, displacement: Vec2, max_y: f32) -> Option<Contact> {
+    let (limit, normal) = match displacement.y {
+        y if y > 0.0 => (max_y, Vec2::NEG_Y),
+        y if y < 0.0 => (-max_y, Vec2::Y),
+        _ => return None,
+    };
+    let time = ((limit - position.y) / displacement.y).max(0.0);
+
+    (time <= 1.0).then_some(Contact { time, normal })
+}
+
+/// Predicts the height at which a ball reaches the vertical line at `x`, bouncing off the top and
+/// bottom walls on the way.
+///
+/// `max_y` is the highest position of the ball center. Returns `None` if the ball moves away
+/// from the line. Paddles are ignored.
+pub fn predict_crossing(position: Vec2, velocity: Vec2, x: f32, max_y: f32) -> Option<f32> {
+    let time = (x - position.x) / velocity.x;
+    if !time.is_finite() || time < 0.0 {
+        return None;
+    }
+
+    // Unfolding the reflections turns the path into a straight line, which is folded back into
+    // the field afterwards.
+    let span = 2.0 * max_y;
+    let unfolded = (position.y + velocity.y * time + max_y).rem_euclid(2.0 * span);
+    let folded = if unfolded > span {
+        2.0 * span - unfolded
+    } else {
+        unfolded
+    };
+
+    Some(folded - max_y)
+}
+
+/// Result of moving the ball by one tick.
+pub struct BallStep {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// Indices of the paddles the ball bounced off, in the order of the contacts.
+    pub paddle_hits: Vec<usize>,
+}
+
+/// Moves the ball for `dt` seconds, bouncing off the top and bottom walls and the paddles.
+///
+/// The ball is reflected at every contact. After a paddle contact `on_paddle_hit` gets the index
+/// of the paddle, the contact position and the reflected velocity, and returns the velocity the
+/// ball continues with.
+pub fn step_ball(
+    ball: Aabb,
+    velocity: Vec2,
+    dt: f32,
+    max_y: f32,
+    paddles: &[Aabb],
+    mut on_paddle_hit: impl FnMut(usize, Vec2, Vec2) -> Vec2,
+) -> BallStep {
+    let mut position = ball.center;
+    let mut velocity = velocity;
+    let mut remaining = dt;
+    let mut paddle_hits = Vec::new();
+
+    for _ in 0..MAX_CONTACTS {
+        let displacement = velocity * remaining;
+        let moving = Aabb {
+            center: position,
+            ..ball
+        };
+        let approaching = |contact: &Contact| velocity.dot(contact.normal) < 0.0;
+
+        let mut first = sweep_walls(position, displacement, max_y)
+            .filter(approaching)
+            .map(|contact| (contact, None));
+        for (index, &paddle) in paddles.iter().enumerate() {
+            let Some(contact) = sweep(moving, displacement, paddle).filter(approaching) else {
+                continue;
+            };
+            if first.is_none_or(|(first, _)| contact.time < first.time) {
+                first = Some((contact, Some(index)));
+            }
+        }
+
+        let Some((contact, paddle)) = first else {
+            position += displacement;
+            remaining = 0.0;
+            break;
+        };
+        position += displacement * contact.time;
+        remaining *= 1.0 - contact.time;
+        velocity -= 2.0 * velocity.dot(contact.normal) * contact.normal;
+        if let Some(index) = paddle {
+            velocity = on_paddle_hit(index, position, velocity);
+            paddle_hits.push(index);
+        }
+    }
+    if remaining > 0.0 {
+        debug!("dropped {remaining}s of ball movement after {MAX_CONTACTS} contacts");
+    }
+    position.y = position.y.clamp(-max_y, max_y);
+
+    BallStep {
+        position,
+        velocity,
+        paddle_hits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_Y: f32 = 1000.0;
+
+    fn reflect(_: usize, _: Vec2, velocity: Vec2) -> Vec2 {
+        velocity
+    }
+
+    #[test]
+    fn sweep_finds_time_of_impact_and_normal() {
+        let moving = Aabb::new(Vec2::ZERO, Vec2::splat(10.0));
+
+        let obstacle = Aabb::new(Vec2::new(50.0, 0.0), Vec2::new(10.0, 40.0));
+        let contact = sweep(moving, Vec2::new(100.0, 0.0), obstacle);
+        assert_eq!(
+            contact,
+            Some(Contact {
+                time: 0.4,
+                normal: Vec2::NEG_X
+            })
+        );
+
+        let obstacle = Aabb::new(Vec2::new(0.0, -50.0), Vec2::new(40.0, 10.0));
+        let contact = sweep(moving, Vec2::new(0.0, -100.0), obstacle);
+        assert_eq!(
+            contact,
+            Some(Contact {
+                time: 0.4,
+                normal: Vec2::Y
+            })
+        );
+
+        let contact = sweep(moving, Vec2::new(0.0, 30.0), obstacle);
+        assert_eq!(contact, None);
+    }
+
+    #[test]
+    fn sweep_touches_overlapping_box_immediately() {
+        let obstacle = Aabb::new(Vec2::ZERO, Vec2::new(10.0, 100.0));
+        let moving = Aabb::new(Vec2::new(6.0, 0.0), Vec2::splat(10.0));
+
+        let contact = sweep(moving, Vec2::new(100.0, 0.0), obstacle);
+        assert_eq!(
+            contact,
+            Some(Contact {
+                time: 0.0,
+                normal: Vec2::X
+            })
+        );
+    }
+
+    #[test]
+    fn fast_ball_does_not_tunnel_through_paddle() {
+        let ball = Aabb::new(Vec2::ZERO, Vec2::splat(10.0));
+        let paddle = Aabb::new(Vec2::new(80.0, 0.0), Vec2::new(10.0, 100.0));
+        // The ball moves about 166 units in this tick, way more than the paddle is wide.
+        let velocity = Vec2::new(10_000.0, 0.0);
+
+        let step = step_ball(ball, velocity, 1.0 / 60.0, MAX_Y, &[paddle], reflect);
+        assert_eq!(step.paddle_hits, [0]);
+        assert_eq!(step.velocity, Vec2::new(-10_000.0, 0.0));
+        assert!(step.position.x < 70.0);
+    }
+
+    #[test]
+    fn receding_ball_is_not_reflected() {
+        let paddle = Aabb::new(Vec2::ZERO, Vec2::new(10.0, 100.0));
+        // Still overlapping the paddle, but already moving away from it.
+        let ball = Aabb::new(Vec2::new(6.0, 0.0), Vec2::splat(10.0));
+        let velocity = Vec2::new(100.0, 0.0);
+
+        let step = step_ball(ball, velocity, 0.1, MAX_Y, &[paddle], reflect);
+        assert!(step.paddle_hits.is_empty());
+        assert_eq!(step.velocity, velocity);
+        assert_eq!(step.position, Vec2::new(16.0, 0.0));
+    }
+
+    #[test]
+    fn bounced_ball_is_not_reflected_twice() {
+        let ball = Aabb::new(Vec2::ZERO, Vec2::splat(10.0));
+        let paddle = Aabb::new(Vec2::new(80.0, 0.0), Vec2::new(10.0, 100.0));
+        let velocity = Vec2::new(10_000.0, 0.0);
+
+        let first = step_ball(ball, velocity, 1.0 / 60.0, MAX_Y, &[paddle], reflect);
+        // Start the next tick still overlapping the paddle a bit, as rounding can leave it.
+        let touching = Aabb::new(Vec2::new(70.5, 0.0), Vec2::splat(10.0));
+        let second = step_ball(
+            touching,
+            first.velocity,
+            1.0 / 60.0,
+            MAX_Y,
+            &[paddle],
+            reflect,
+        );
+        assert!(second.paddle_hits.is_empty());
+        assert_eq!(second.velocity, first.velocity);
+    }
+
+    #[test]
+    fn contacts_per_tick_are_capped() {
+        let ball = Aabb::new(Vec2::ZERO, Vec2::splat(2.0));
+        let paddles = [
+            Aabb::new(Vec2::new(-10.0, 0.0), Vec2::new(2.0, 100.0)),
+            Aabb::new(Vec2::new(10.0, 0.0), Vec2::new(2.0, 100.0)),
+        ];
+        // Enough movement for dozens of bounces between the paddles.
+        let velocity = Vec2::new(1000.0, 0.0);
+
+        let step = step_ball(ball, velocity, 1.0, MAX_Y, &paddles, reflect);
+        assert_eq!(step.paddle_hits, [1, 0, 1, 0][..MAX_CONTACTS]);
+        assert!(step.position.x.abs() <= 8.0);
+    }
+}
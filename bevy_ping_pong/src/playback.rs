@@ -0,0 +1,340 @@
+//! Playback of recorded matches on the client.
+//!
+//! The [`ReplayPlaybackPlugin`] spawns the ball, the paddles and the match entity of a
+//! [`Replay`] like the server would and moves them to the recorded positions, so the client
+//! draws them with its usual systems and shows the score as a spectator. Nothing is simulated,
+//! seeking just jumps to another frame.
+//!
+//! Space pauses and resumes, the left and right arrow keys seek and the up and down arrow keys
+//! change the speed. With egui the same controls are shown in a window, together with the match
+//! messages up to the current frame.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_replicon::renet::ClientId;
+
+use crate::{
+    player_color,
+    replay::Replay,
+    room::{CurrentRoom, MatchBundle, RoomId},
+    scoring::{Score, Serve},
+    tick::{SimulationTick, TickRate},
+    Ball, BallBundle, GameData, GameState, LocalData, Paddle, Player, PlayerBundle, PlayerPosition,
+    S2cMessage,
+};
+
+/// Room the replayed match is shown in.
+const REPLAY_ROOM: RoomId = RoomId(1);
+
+/// Seconds a single seek jumps.
+const SEEK_SECS: f64 = 5.0;
+
+const MIN_SPEED: f32 = 0.125;
+const MAX_SPEED: f32 = 8.0;
+
+/// Match messages listed in the controls window.
+const SHOWN_MESSAGES: usize = 8;
+
+/// Plays a [`Replay`] back, see the module docs.
+///
+/// Needs the [`PingPongSharedPlugin`](crate::PingPongSharedPlugin) and the
+/// [`PingPongClientPlugin`](crate::PingPongClientPlugin) but neither a server nor a connection.
+pub struct ReplayPlaybackPlugin {
+    pub replay: Replay,
+}
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        let replay = &self.replay;
+        // The viewer watches as a spectator.
+        let viewer_id = (0..)
+            .find(|&client_id| replay.player(client_id).is_none())
+            .expect("a replay has only two players");
+        app.insert_resource(LocalData {
+            client_id: viewer_id,
+        })
+        .insert_resource(replay.rules.clone())
+        .insert_resource(TickRate(replay.tick_rate))
+        .insert_resource(ReplayPlayback {
+            replay: replay.clone(),
+            position: 0.0,
+            speed: 1.0,
+            paused: false,
+        })
+        .add_systems(Startup, init_system)
+        .add_systems(
+            Update,
+            (
+                keyboard_system,
+                controls_system.run_if(any_with_component::<EguiContext>()),
+                advance_system,
+                show_frame_system,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Progress of the playback.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    /// Index of the shown frame, the fraction is the progress towards the next one.
+    position: f64,
+    /// Recorded seconds played per real second.
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl ReplayPlayback {
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Index of the shown frame.
+    pub fn frame_index(&self) -> usize {
+        self.position as usize
+    }
+
+    /// Jumps to a frame, clamped to the recorded ones.
+    pub fn seek(&mut self, index: usize) {
+        self.position = index.min(self.last_index()) as f64;
+    }
+
+    /// Moves by a number of recorded seconds, backwards if negative.
+    pub fn skip(&mut self, seconds: f64) {
+        let position = self.position + seconds * self.replay.tick_rate as f64;
+        self.position = position.clamp(0.0, self.last_index() as f64);
+    }
+
+    fn last_index(&self) -> usize {
+        self.replay.frames.len().saturating_sub(1)
+    }
+
+    fn change_speed(&mut self, factor: f32) {
+        self.speed = (self.speed * factor).clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Recorded time of the shown frame in seconds.
+    fn seconds(&self) -> f32 {
+        self.replay.seconds(self.frame_index())
+    }
+
+    /// Describes the match messages up to the shown frame, newest last.
+    fn recent_messages(&self) -> Vec<String> {
+        let replay = &self.replay;
+        let name_of = |client_id: u64| {
+            replay.player(client_id).map_or_else(
+                || format!("Client {client_id}"),
+                |player| player.name.clone(),
+            )
+        };
+        let mut messages: Vec<_> = replay.frames[..=self.frame_index().min(self.last_index())]
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(index, frame)| frame.messages.iter().rev().map(move |msg| (index, msg)))
+            .take(SHOWN_MESSAGES)
+            .map(|(index, msg)| {
+                let text = match *msg {
+                    S2cMessage::GameStart(..) => "match started".to_string(),
+                    S2cMessage::RoundResult(actor) => {
+                        let name = replay
+                            .actor(actor)
+                            .map_or("nobody", |player| player.name.as_str());
+                        format!("point for {name}")
+                    }
+                    S2cMessage::GameEnd => "match over".to_string(),
+                    S2cMessage::PlayerDisconnected(client_id) => {
+                        format!("{} lost their connection", name_of(client_id))
+                    }
+                    S2cMessage::PlayerReconnected(client_id) => {
+                        format!("{} reconnected", name_of(client_id))
+                    }
+                    S2cMessage::Forfeit(client_id) => {
+                        format!("{} wins by forfeit", name_of(client_id))
+                    }
+                    S2cMessage::RematchVote(client_id) => {
+                        format!("{} wants a rematch", name_of(client_id))
+                    }
+                    S2cMessage::PlayerLeft(client_id) => {
+                        format!("{} left the match", name_of(client_id))
+                    }
+                    ref msg => format!("{msg:?}"),
+                };
+                format!("{:>6.1} s  {text}", replay.seconds(index))
+            })
+            .collect();
+        messages.reverse();
+
+        messages
+    }
+}
+
+fn init_system(
+    mut commands: Commands,
+    mut current_room: ResMut<CurrentRoom>,
+    playback: Res<ReplayPlayback>,
+) {
+    let replay = &playback.replay;
+    let Some(first) = replay.frames.first() else {
+        warn!("the replay contains no frames");
+        return;
+    };
+
+    let [left, right] = &replay.players;
+    commands
+        .spawn(MatchBundle::new(REPLAY_ROOM, first.serve))
+        .insert(GameData {
+            player_count: 2,
+            actor1: left.client_id,
+            actor2: right.client_id,
+            phase: GameState::Game,
+            ..Default::default()
+        });
+    commands.spawn(SimulationTick {
+        tick: first.tick,
+        rate: replay.tick_rate,
+    });
+    commands.spawn(BallBundle::new(
+        REPLAY_ROOM,
+        first.ball,
+        Vec2::ZERO,
+        Color::rgb(1.0, 1.0, 1.0),
+    ));
+    for (player, position) in replay.players.iter().zip(first.paddles) {
+        commands.spawn(PlayerBundle::new(
+            ClientId::from_raw(player.client_id),
+            REPLAY_ROOM,
+            player.name.clone(),
+            position,
+            player_color(player.client_id),
+        ));
+    }
+    current_room.0 = Some(REPLAY_ROOM);
+}
+
+fn keyboard_system(input: Res<Input<KeyCode>>, mut playback: ResMut<ReplayPlayback>) {
+    if input.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+    if input.just_pressed(KeyCode::Left) {
+        playback.skip(-SEEK_SECS);
+    }
+    if input.just_pressed(KeyCode::Right) {
+        playback.skip(SEEK_SECS);
+    }
+    if input.just_pressed(KeyCode::Up) {
+        playback.change_speed(2.0);
+    }
+    if input.just_pressed(KeyCode::Down) {
+        playback.change_speed(0.5);
+    }
+}
+
+/// Shows the playback controls and the recent match messages.
+fn controls_system(mut egui_ctx: Query<&mut EguiContext>, mut playback: ResMut<ReplayPlayback>) {
+    let last_index = playback.last_index();
+    let duration = playback.replay.seconds(last_index);
+    let messages = playback.recent_messages();
+    egui::Window::new("Replay")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -10.0])
+        .resizable(false)
+        .show(egui_ctx.single_mut().get_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let label = if playback.paused { "Play" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    playback.paused = !playback.paused;
+                }
+                if ui.button("<<").clicked() {
+                    playback.skip(-SEEK_SECS);
+                }
+                if ui.button(">>").clicked() {
+                    playback.skip(SEEK_SECS);
+                }
+                if ui.button("Slower").clicked() {
+                    playback.change_speed(0.5);
+                }
+                if ui.button("Faster").clicked() {
+                    playback.change_speed(2.0);
+                }
+                ui.label(format!(
+                    "{:.1} / {duration:.1} s at {}x",
+                    playback.seconds(),
+                    playback.speed
+                ));
+            });
+
+            let mut index = playback.frame_index();
+            let slider = egui::Slider::new(&mut index, 0..=last_index).show_value(false);
+            if ui.add(slider).changed() {
+                playback.seek(index);
+            }
+
+            for message in &messages {
+                ui.monospace(message);
+            }
+        });
+}
+
+/// Moves the playback forward with the real time, it pauses at the end of the replay.
+fn advance_system(time: Res<Time>, mut playback: ResMut<ReplayPlayback>) {
+    if playback.paused {
+        return;
+    }
+    let frames = time.delta_seconds_f64() * playback.replay.tick_rate as f64;
+    let position = playback.position + frames * playback.speed as f64;
+    let last_index = playback.last_index() as f64;
+    if position >= last_index {
+        playback.paused = true;
+    }
+    playback.position = position.min(last_index);
+}
+
+/// Puts the ball, the paddles and the score of the shown frame on the board.
+fn show_frame_system(
+    playback: Res<ReplayPlayback>,
+    mut balls: Query<&mut PlayerPosition, (With<Ball>, Without<Paddle>)>,
+    mut paddles: Query<(&Player, &mut PlayerPosition), (With<Paddle>, Without<Ball>)>,
+    mut matches: Query<(&mut Score, &mut Serve, &mut GameData)>,
+    mut ticks: Query<&mut SimulationTick>,
+) {
+    let replay = &playback.replay;
+    let Some(frame) = replay.frames.get(playback.frame_index()) else {
+        return;
+    };
+
+    for mut ball in &mut balls {
+        if **ball != frame.ball {
+            **ball = frame.ball;
+        }
+    }
+    for (player, mut position) in &mut paddles {
+        if let Some(index) = replay
+            .players
+            .iter()
+            .position(|replay_player| replay_player.client_id == player.0.raw())
+        {
+            if **position != frame.paddles[index] {
+                **position = frame.paddles[index];
+            }
+        }
+    }
+    for (mut score, mut serve, mut game_data) in &mut matches {
+        score.set_if_neq(frame.score);
+        serve.set_if_neq(frame.serve);
+        let phase = if frame.paused {
+            GameState::Paused
+        } else {
+            GameState::Game
+        };
+        if game_data.phase != phase {
+            game_data.phase = phase;
+        }
+    }
+    for mut tick in &mut ticks {
+        if tick.tick != frame.tick {
+            tick.tick = frame.tick;
+        }
+    }
+}
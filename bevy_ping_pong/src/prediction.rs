@@ -0,0 +1,156 @@
+//! Client-side prediction of the local paddle.
+//!
+//! Every [`MoveDirection`](crate::MoveDirection) a client sends carries a sequence number. The
+//! server moves the paddle and stores the last processed sequence number in the replicated
+//! [`InputAck`] of the paddle. The client moves its paddle as soon as it sends an input and keeps
+//! the input in its [`InputHistory`] until it's acknowledged. Whenever an authoritative position
+//! arrives the client starts from it and replays the inputs the server hasn't processed yet.
+//!
+//! Clients sample one input per simulation tick and the server applies at most one input of a
+//! client per tick, so both move the paddle by the same amount for every input.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::rules::MatchRules;
+
+/// Inputs kept for replay at most, older ones are dropped when the server stops acknowledging.
+const MAX_PENDING_INPUTS: usize = 120;
+
+/// Sequence number of the last input the server applied to the paddle.
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Serialize)]
+pub struct InputAck(pub u32);
+
+/// Position of the local paddle predicted from the inputs the server hasn't processed yet.
+#[derive(Clone, Copy, Component, Debug, Deref, DerefMut)]
+pub struct PredictedPosition(pub Vec2);
+
+/// Inputs the local client sent but the server hasn't acknowledged yet.
+#[derive(Resource, Default)]
+pub struct InputHistory {
+    last_sequence: u32,
+    pending: VecDeque<(u32, Vec2)>,
+}
+
+impl InputHistory {
+    /// Stores a new input and returns its sequence number.
+    pub fn record(&mut self, direction: Vec2) -> u32 {
+        self.last_sequence = self.last_sequence.wrapping_add(1);
+        if self.pending.len() == MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((self.last_sequence, direction));
+
+        self.last_sequence
+    }
+
+    /// Forgets all inputs up to and including `sequence`.
+    pub fn acknowledge(&mut self, sequence: u32) {
+        while self
+            .pending
+            .front()
+            .is_some_and(|&(pending_sequence, _)| pending_sequence <= sequence)
+        {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Applies the unacknowledged inputs to an authoritative paddle position, one tick of `dt`
+    /// seconds each.
+    pub fn replay(&self, position: Vec2, rules: &MatchRules, dt: f32) -> Vec2 {
+        self.pending
+            .iter()
+            .fold(position, |position, &(_, direction)| {
+                rules.move_paddle(position, direction, dt)
+            })
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    #[test]
+    fn sequence_numbers_increase() {
+        let mut history = InputHistory::default();
+        assert_eq!(history.record(Vec2::Y), 1);
+        assert_eq!(history.record(Vec2::NEG_Y), 2);
+        assert_eq!(history.record(Vec2::Y), 3);
+    }
+
+    #[test]
+    fn replay_applies_unacknowledged_inputs() {
+        let rules = MatchRules::default();
+        let mut history = InputHistory::default();
+        for _ in 0..3 {
+            history.record(Vec2::Y);
+        }
+        history.record(Vec2::NEG_Y);
+
+        let step = rules.paddle_speed * DT;
+        let position = history.replay(Vec2::ZERO, &rules, DT);
+        assert!((position.y - 2.0 * step).abs() < 1e-4);
+
+        // The server applied the first two inputs and sent back the position after them.
+        history.acknowledge(2);
+        let position = history.replay(Vec2::new(0.0, 2.0 * step), &rules, DT);
+        assert!((position.y - 2.0 * step).abs() < 1e-4);
+    }
+
+    #[test]
+    fn acknowledge_drops_inputs_up_to_the_sequence() {
+        let rules = MatchRules::default();
+        let mut history = InputHistory::default();
+        for _ in 0..5 {
+            history.record(Vec2::Y);
+        }
+
+        history.acknowledge(3);
+        assert_eq!(history.pending.len(), 2);
+        assert_eq!(
+            history.pending.front().map(|&(sequence, _)| sequence),
+            Some(4)
+        );
+
+        // Acknowledgements that arrive late don't bring inputs back.
+        history.acknowledge(1);
+        assert_eq!(history.pending.len(), 2);
+
+        history.acknowledge(5);
+        assert_eq!(history.replay(Vec2::ZERO, &rules, DT), Vec2::ZERO);
+    }
+
+    #[test]
+    fn replay_stays_on_the_field() {
+        let rules = MatchRules::default();
+        let mut history = InputHistory::default();
+        for _ in 0..MAX_PENDING_INPUTS {
+            history.record(Vec2::Y);
+        }
+
+        let position = history.replay(Vec2::ZERO, &rules, DT);
+        assert_eq!(position.y, rules.paddle_max_y());
+    }
+
+    #[test]
+    fn oldest_inputs_are_dropped_when_never_acknowledged() {
+        let mut history = InputHistory::default();
+        for _ in 0..MAX_PENDING_INPUTS + 10 {
+            history.record(Vec2::Y);
+        }
+
+        assert_eq!(history.pending.len(), MAX_PENDING_INPUTS);
+        assert_eq!(
+            history.pending.front().map(|&(sequence, _)| sequence),
+            Some(11)
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Recording of finished matches.
+//!
+//! With a [`ReplayRecorder`] the server records every match into a [`Replay`]: the rules, the
+//! players, the seed of the serves and one [`ReplayFrame`] per simulated tick with the positions
+//! of the ball and the paddles, the score, the inputs applied in the tick and the match messages
+//! sent to the room. Once the match is over, or its room closed, the replay is written into the
+//! directory of the recorder.
+//!
+//! Replay files start with a magic number and the [`REPLAY_VERSION`], followed by the replay
+//! encoded with bincode. Files of another version are refused instead of misread. The client
+//! plays them back with the [`ReplayPlaybackPlugin`](crate::playback::ReplayPlaybackPlugin).
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::prelude::*;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    room::{RoomId, RoomMessage, Rooms},
+    rules::MatchRules,
+    scoring::{Score, Serve},
+    tick::TickRate,
+    AppliedInputs, Ball, GameState, Paddle, Player, PlayerName, PlayerPosition, S2cMessage,
+};
+
+/// Version of the replay format, increased whenever the encoding of a [`Replay`] changes.
+pub const REPLAY_VERSION: u16 = 1;
+
+/// Extension of the files written by the [`ReplayRecorder`].
+pub const REPLAY_EXTENSION: &str = "replay";
+
+/// First bytes of every replay file.
+const MAGIC: &[u8; 4] = b"PPRP";
+
+/// A recorded match.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Replay {
+    pub rules: MatchRules,
+    /// Simulation ticks per second.
+    pub tick_rate: u16,
+    /// Seed of the generator that picked the serves of the match.
+    pub seed: u64,
+    /// The left and the right player.
+    pub players: [ReplayPlayer; 2],
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReplayPlayer {
+    pub client_id: u64,
+    pub name: String,
+}
+
+/// State of the match after a simulated tick.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReplayFrame {
+    pub tick: u32,
+    /// The match waited for a disconnected player instead of simulating the tick.
+    pub paused: bool,
+    pub ball: Vec2,
+    /// Positions of the left and the right paddle.
+    pub paddles: [Vec2; 2],
+    pub score: Score,
+    pub serve: Serve,
+    /// Inputs applied in the tick.
+    pub inputs: Vec<ReplayInput>,
+    /// Match messages sent to the room since the previous frame.
+    pub messages: Vec<S2cMessage>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ReplayInput {
+    pub client_id: u64,
+    pub direction: Vec2,
+}
+
+impl Replay {
+    /// Writes the replay to a file, see the module docs for the format.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        bincode::DefaultOptions::new().serialize_into(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads a replay written by [`Replay::save`].
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("not a replay file".into());
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != REPLAY_VERSION {
+            return Err(format!(
+                "replay version {version} is not supported, expected {REPLAY_VERSION}"
+            )
+            .into());
+        }
+        let replay = bincode::DefaultOptions::new().deserialize_from(reader)?;
+
+        Ok(replay)
+    }
+
+    /// Player on the side named in a [`S2cMessage::RoundResult`], 1 for the left one.
+    pub fn actor(&self, actor: u64) -> Option<&ReplayPlayer> {
+        match actor {
+            1 => Some(&self.players[0]),
+            2 => Some(&self.players[1]),
+            _ => None,
+        }
+    }
+
+    pub fn player(&self, client_id: u64) -> Option<&ReplayPlayer> {
+        self.players
+            .iter()
+            .find(|player| player.client_id == client_id)
+    }
+
+    /// Recorded time of a frame in seconds since the start of the match.
+    pub fn seconds(&self, index: usize) -> f32 {
+        index as f32 / self.tick_rate as f32
+    }
+}
+
+/// Returns whether the message is about the match of a room and belongs into its replay.
+fn is_match_message(msg: &S2cMessage) -> bool {
+    matches!(
+        msg,
+        S2cMessage::GameStart(..)
+            | S2cMessage::RoundResult(_)
+            | S2cMessage::GameEnd
+            | S2cMessage::PlayerDisconnected(_)
+            | S2cMessage::PlayerReconnected(_)
+            | S2cMessage::Forfeit(_)
+            | S2cMessage::RematchVote(_)
+            | S2cMessage::PlayerLeft(_)
+    )
+}
+
+/// Records the matches of the server, see the module docs.
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    dir: PathBuf,
+    recordings: HashMap<RoomId, Recording>,
+    /// Messages of rooms whose match didn't record a frame yet.
+    pending_messages: HashMap<RoomId, Vec<S2cMessage>>,
+}
+
+struct Recording {
+    replay: Replay,
+    path: PathBuf,
+    /// The match is over, no more frames are recorded.
+    ended: bool,
+    saved: bool,
+}
+
+impl Recording {
+    fn save(&mut self) {
+        if self.saved {
+            return;
+        }
+        self.saved = true;
+        match self.replay.save(&self.path) {
+            Ok(()) => info!("saved replay {}", self.path.display()),
+            Err(e) => error!("unable to save replay {}: {e}", self.path.display()),
+        }
+    }
+}
+
+impl ReplayRecorder {
+    /// Records into `dir`, which has to exist.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            recordings: HashMap::default(),
+            pending_messages: HashMap::default(),
+        }
+    }
+}
+
+/// Path of the replay of a match that starts now.
+fn replay_path(dir: &Path, room_id: RoomId) -> PathBuf {
+    let started = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    dir.join(format!("{started}-room{}.{REPLAY_EXTENSION}", room_id.0))
+}
+
+/// Records a frame of every running match after each simulated tick.
+pub(crate) fn record_ticks_system(
+    rules: Res<MatchRules>,
+    tick_rate: Res<TickRate>,
+    replicon_tick: Res<RepliconTick>,
+    rooms: Res<Rooms>,
+    applied_inputs: Res<AppliedInputs>,
+    mut recorder: ResMut<ReplayRecorder>,
+    paddles: Query<(&Player, &RoomId, &PlayerPosition, &PlayerName), With<Paddle>>,
+    balls: Query<(&RoomId, &PlayerPosition), With<Ball>>,
+    matches: Query<(&RoomId, &Score, &Serve)>,
+) {
+    let recorder = &mut *recorder;
+    for (room_id, room) in rooms.iter() {
+        let game_data = &room.game_data;
+        let running = matches!(game_data.phase, GameState::Game | GameState::Paused);
+        let recording_ended = recorder
+            .recordings
+            .get(&room_id)
+            .is_none_or(|recording| recording.ended);
+        if recording_ended {
+            if !running {
+                continue;
+            }
+            // A rematch starts a new replay.
+            let players = [game_data.actor1, game_data.actor2].map(|client_id| {
+                let name = paddles
+                    .iter()
+                    .find(|(player, paddle_room_id, ..)| {
+                        player.0.raw() == client_id && **paddle_room_id == room_id
+                    })
+                    .map_or_else(
+                        || format!("Player {client_id}"),
+                        |(.., name)| name.0.clone(),
+                    );
+                ReplayPlayer { client_id, name }
+            });
+            let recording = Recording {
+                replay: Replay {
+                    rules: rules.clone(),
+                    tick_rate: tick_rate.0,
+                    seed: room.seed,
+                    players,
+                    frames: Vec::new(),
+                },
+                path: replay_path(&recorder.dir, room_id),
+                ended: false,
+                saved: false,
+            };
+            if let Some(mut previous) = recorder.recordings.insert(room_id, recording) {
+                previous.save();
+            }
+        }
+        let recording = recorder
+            .recordings
+            .get_mut(&room_id)
+            .expect("running match should be recorded");
+
+        let Some((_, ball)) = balls.iter().find(|(id, _)| **id == room_id) else {
+            continue;
+        };
+        let Some((_, score, serve)) = matches.iter().find(|(id, ..)| **id == room_id) else {
+            continue;
+        };
+        // A forfeit ends the match between two ticks, the tick after it wasn't simulated.
+        if !running && !score.is_match_over(&rules) {
+            recording.ended = true;
+            continue;
+        }
+        let paddle_of = |client_id: u64| {
+            paddles
+                .iter()
+                .find(|(player, paddle_room_id, ..)| {
+                    player.0.raw() == client_id && **paddle_room_id == room_id
+                })
+                .map_or(Vec2::ZERO, |(_, _, position, _)| **position)
+        };
+        let inputs = applied_inputs
+            .iter()
+            .filter(|(input_room_id, ..)| *input_room_id == room_id)
+            .map(|&(_, client_id, direction)| ReplayInput {
+                client_id,
+                direction,
+            })
+            .collect();
+        let messages = if recording.replay.frames.is_empty() {
+            recorder.pending_messages.remove(&room_id)
+        } else {
+            None
+        };
+        recording.replay.frames.push(ReplayFrame {
+            tick: replicon_tick.get(),
+            paused: game_data.phase == GameState::Paused,
+            ball: **ball,
+            paddles: [paddle_of(game_data.actor1), paddle_of(game_data.actor2)],
+            score: *score,
+            serve: *serve,
+            inputs,
+            messages: messages.unwrap_or_default(),
+        });
+        recording.ended = !running;
+    }
+}
+
+/// Adds the match messages to the replays, saves the replays of finished matches and forgets
+/// the ones of closed rooms.
+pub(crate) fn record_messages_system(
+    rooms: Res<Rooms>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut room_messages: EventReader<RoomMessage>,
+) {
+    for RoomMessage { room_id, msg } in room_messages.read() {
+        if !is_match_message(msg) {
+            continue;
+        }
+
+        let frame = recorder
+            .recordings
+            .get_mut(room_id)
+            .filter(|recording| !recording.saved)
+            .and_then(|recording| recording.replay.frames.last_mut());
+        match frame {
+            Some(frame) => frame.messages.push(msg.clone()),
+            None => recorder
+                .pending_messages
+                .entry(*room_id)
+                .or_default()
+                .push(msg.clone()),
+        }
+    }
+
+    recorder.recordings.retain(|&room_id, recording| {
+        let closed = rooms.get(room_id).is_none();
+        if recording.ended || closed {
+            recording.save();
+        }
+        !closed
+    });
+    recorder
+        .pending_messages
+        .retain(|&room_id, _| rooms.get(room_id).is_some());
+}
+
+/// Creates the directory replays are recorded into.
+pub fn create_replay_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("unable to create replay directory {}: {e}", dir.display()))?;
+
+    Ok(())
+}
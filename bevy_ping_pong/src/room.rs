@@ -0,0 +1,269 @@
+//! Rooms let one server host many matches at once.
+//!
+//! Every match is a [`Room`] with its own [`GameData`] and [`ReconnectTimers`], and the ball,
+//! paddles and score of the match carry the room's [`RoomId`]. The server opens a room for every
+//! pair of players leaving the lobby and only sends its members the messages of that room.
+//!
+//! The state of the match is replicated on a match entity per room, spawned from a
+//! [`MatchBundle`]. The server replicates the entities of a room only to its members, entities
+//! without a room, like the [`MatchRules`], are replicated to every client.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rules::MatchRules,
+    scoring::{Score, Serve},
+    send_to_clients, BallBundle, GameData, ReconnectTimers, S2cMessage, ServerMessage,
+};
+
+#[derive(
+    Clone, Copy, Component, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub struct RoomId(pub u32);
+
+/// State of a single match on the server.
+pub struct Room {
+    pub(crate) game_data: GameData,
+    /// Seed of `rng` at the start of the current match.
+    pub(crate) seed: u64,
+    pub(crate) reconnect_timers: ReconnectTimers,
+    /// Picks the serves of the match.
+    pub(crate) rng: StdRng,
+}
+
+impl Room {
+    /// Seeds the serves of a new match from the ones of the previous match.
+    ///
+    /// Every match starts from a known seed, so its serves can be repeated from a replay.
+    pub(crate) fn reseed(&mut self) {
+        self.seed = self.rng.gen();
+        self.rng = StdRng::seed_from_u64(self.seed);
+    }
+}
+
+/// Server bookkeeping of the open rooms and the clients in them.
+#[derive(Resource)]
+pub struct Rooms {
+    /// Maximum number of rooms, paired players wait in the lobby while all of them are taken.
+    pub max_rooms: usize,
+    /// Seed of the random serves, every room derives its own generator from it and its id.
+    pub seed: u64,
+    next_id: u32,
+    rooms: HashMap<RoomId, Room>,
+    clients: HashMap<u64, RoomId>,
+}
+
+impl Default for Rooms {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Rooms {
+    pub fn new(max_rooms: usize) -> Self {
+        Self {
+            max_rooms,
+            seed: rand::random(),
+            next_id: 1,
+            rooms: HashMap::default(),
+            clients: HashMap::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.max_rooms
+    }
+
+    pub fn get(&self, room_id: RoomId) -> Option<&Room> {
+        self.rooms.get(&room_id)
+    }
+
+    pub fn get_mut(&mut self, room_id: RoomId) -> Option<&mut Room> {
+        self.rooms.get_mut(&room_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RoomId, &Room)> {
+        self.rooms.iter().map(|(&room_id, room)| (room_id, room))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (RoomId, &mut Room)> {
+        self.rooms
+            .iter_mut()
+            .map(|(&room_id, room)| (room_id, room))
+    }
+
+    /// Returns the room the client plays or spectates in.
+    ///
+    /// Players who lost their connection stay assigned to their room until their slot is freed.
+    pub fn room_of(&self, client_id: u64) -> Option<RoomId> {
+        self.clients.get(&client_id).copied()
+    }
+
+    /// Creates an empty room and spawns its ball and match entity.
+    pub(crate) fn open(
+        &mut self,
+        commands: &mut Commands,
+        rules: &MatchRules,
+        serve: Serve,
+    ) -> RoomId {
+        let seed = self.seed ^ u64::from(self.next_id);
+        self.open_with_seed(commands, rules, serve, seed)
+    }
+
+    /// Opens a room whose serves are picked by a generator seeded with `seed`.
+    pub(crate) fn open_with_seed(
+        &mut self,
+        commands: &mut Commands,
+        rules: &MatchRules,
+        serve: Serve,
+        seed: u64,
+    ) -> RoomId {
+        let room_id = RoomId(self.next_id);
+        self.next_id += 1;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let direction = if rng.gen() { 1.0 } else { -1.0 };
+        commands.spawn(BallBundle::new(
+            room_id,
+            Vec2::ZERO,
+            rules.serve_velocity(&mut rng, direction),
+            Color::rgb(1.0, 1.0, 1.0),
+        ));
+        commands.spawn(MatchBundle::new(room_id, serve));
+        self.rooms.insert(
+            room_id,
+            Room {
+                game_data: GameData::default(),
+                seed,
+                reconnect_timers: ReconnectTimers::default(),
+                rng,
+            },
+        );
+        info!("opened room {}", room_id.0);
+
+        room_id
+    }
+
+    /// Removes rooms nobody is left in and returns their ids.
+    ///
+    /// The ball and paddles of a room stay on the board until it's closed.
+    pub(crate) fn close_deserted(&mut self) -> Vec<RoomId> {
+        let mut closed = Vec::new();
+        self.rooms.retain(|&room_id, room| {
+            if !room.game_data.is_deserted() || !room.reconnect_timers.is_empty() {
+                return true;
+            }
+            info!("closed room {}", room_id.0);
+            closed.push(room_id);
+            false
+        });
+
+        closed
+    }
+
+    pub(crate) fn join(&mut self, client_id: u64, room_id: RoomId) {
+        self.clients.insert(client_id, room_id);
+    }
+
+    pub(crate) fn leave(&mut self, client_id: u64) {
+        self.clients.remove(&client_id);
+    }
+}
+
+/// Replicated state of the match in a room.
+#[derive(Bundle)]
+pub struct MatchBundle {
+    room_id: RoomId,
+    game_data: GameData,
+    score: Score,
+    serve: Serve,
+    replication: Replication,
+}
+
+impl MatchBundle {
+    pub fn new(room_id: RoomId, serve: Serve) -> Self {
+        Self {
+            room_id,
+            game_data: GameData::default(),
+            score: Score::default(),
+            serve,
+            replication: Replication,
+        }
+    }
+}
+
+/// The room the local client is in, assigned by the server.
+#[derive(Resource, Default)]
+pub struct CurrentRoom(pub Option<RoomId>);
+
+/// Replicates the entities of every room only to the clients in it.
+///
+/// The server uses [`VisibilityPolicy::Whitelist`], so entities nobody was given access to aren't
+/// replicated at all. A client leaving a room loses its entities, which despawns them on its side.
+pub(crate) fn visibility_system(
+    rooms: Res<Rooms>,
+    mut client_cache: ResMut<ClientCache>,
+    entities: Query<(Entity, Option<&RoomId>), With<Replication>>,
+) {
+    for client in client_cache.iter_mut() {
+        let room_of_client = rooms.room_of(client.id().raw());
+        let visibility = client.visibility_mut();
+        for (entity, room_id) in &entities {
+            let visible = room_id.is_none_or(|room_id| room_of_client == Some(*room_id));
+            visibility.set_visibility(entity, visible);
+        }
+    }
+}
+
+/// A message sent to the members of a room, read by the replay recorder.
+///
+/// Unlike the messages to the clients, which leave out bots and absent players, it's sent even
+/// if nobody in the room receives the message.
+#[derive(Clone, Debug, Event)]
+pub(crate) struct RoomMessage {
+    pub(crate) room_id: RoomId,
+    pub(crate) msg: S2cMessage,
+}
+
+/// Sends a message to every connected player and spectator of a room.
+pub(crate) fn send_to_room(
+    game_message_events: &mut EventWriter<ToClients<ServerMessage>>,
+    room_messages: &mut EventWriter<RoomMessage>,
+    room_id: RoomId,
+    game_data: &GameData,
+    msg: S2cMessage,
+) {
+    room_messages.send(RoomMessage {
+        room_id,
+        msg: msg.clone(),
+    });
+    send_to_clients(game_message_events, game_data.members(), msg);
+}
+
+impl GameData {
+    /// Connected clients taking part in the match, players first.
+    pub(crate) fn members(&self) -> impl Iterator<Item = u64> + '_ {
+        [self.actor1, self.actor2]
+            .into_iter()
+            .filter(|&id| !self.absent.contains(&id) && !self.left.contains(&id))
+            .chain(self.spectators.iter().copied())
+    }
+
+    /// Returns whether nobody is left in the room.
+    pub(crate) fn is_deserted(&self) -> bool {
+        [self.actor1, self.actor2]
+            .iter()
+            .all(|id| self.left.contains(id))
+            && self.spectators.is_empty()
+    }
+}
@@ -0,0 +1,364 @@
+//! Match rules shared by the server and its clients.
+//!
+//! The server loads [`MatchRules`] at startup and replicates them on a dedicated entity.
+//! Clients copy the replicated component into their own [`MatchRules`] resource, so every
+//! system reads the same rules on both sides.
+
+use std::{error::Error, fs, path::Path};
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How a match is decided.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum WinCondition {
+    /// A fixed number of rounds is played, the player with the most points wins.
+    Rounds(u16),
+    /// The first player to reach the score wins.
+    TargetScore(u16),
+}
+
+#[derive(Clone, Component, Debug, Deserialize, PartialEq, Resource, Serialize)]
+#[serde(default)]
+pub struct MatchRules {
+    pub win_condition: WinCondition,
+    /// The match only ends once one player leads by at least two points.
+    pub win_by_two: bool,
+    /// Ball speed at the start of every round, in units per second.
+    pub ball_speed: f32,
+    /// Fraction of its speed the ball gains on every paddle hit.
+    pub speed_up_per_hit: f32,
+    /// The ball doesn't speed up beyond this, in units per second.
+    pub max_ball_speed: f32,
+    /// Angle of the ball leaving a paddle hit at its edge, in degrees. Hits closer to the center
+    /// of the paddle send the ball back flatter.
+    pub max_bounce_angle: f32,
+    /// Fraction of the vertical paddle velocity passed on to the ball on a hit.
+    pub paddle_spin: f32,
+    /// Serves leave the center at a random angle up to this, in degrees.
+    pub max_serve_angle: f32,
+    /// How long the ball waits in the center before it's served, in seconds.
+    pub serve_delay_secs: f32,
+    /// Paddle speed, in units per second.
+    pub paddle_speed: f32,
+    pub paddle_size: Vec2,
+    /// Side length of the square ball.
+    pub ball_size: f32,
+    pub field_size: Vec2,
+    /// How long the slot of a disconnected player is held before they forfeit, in seconds.
+    pub reconnect_grace_secs: f32,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            win_condition: WinCondition::Rounds(3),
+            win_by_two: false,
+            ball_speed: 150.0 * std::f32::consts::SQRT_2,
+            speed_up_per_hit: 0.05,
+            max_ball_speed: 600.0,
+            max_bounce_angle: 60.0,
+            paddle_spin: 0.5,
+            max_serve_angle: 30.0,
+            serve_delay_secs: 2.0,
+            paddle_speed: 150.0,
+            paddle_size: Vec2::new(50.0, 250.0),
+            ball_size: 50.0,
+            field_size: Vec2::new(1280.0, 720.0),
+            reconnect_grace_secs: 30.0,
+        }
+    }
+}
+
+impl MatchRules {
+    /// Loads rules from a RON file, missing fields keep their default value.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let rules = ron::from_str(&text)?;
+
+        Ok(rules)
+    }
+
+    /// Checks that the rules describe a playable field.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.win_condition {
+            WinCondition::Rounds(0) => return Err("a match needs at least one round".into()),
+            WinCondition::TargetScore(0) => return Err("the target score must be positive".into()),
+            _ => (),
+        }
+        // NaN passes none of the comparisons below.
+        let values = [
+            self.ball_speed,
+            self.speed_up_per_hit,
+            self.max_ball_speed,
+            self.max_bounce_angle,
+            self.paddle_spin,
+            self.max_serve_angle,
+            self.serve_delay_secs,
+            self.paddle_speed,
+            self.paddle_size.x,
+            self.paddle_size.y,
+            self.ball_size,
+            self.field_size.x,
+            self.field_size.y,
+            self.reconnect_grace_secs,
+        ];
+        if !values.iter().all(|value| value.is_finite()) {
+            return Err("all values must be finite numbers".into());
+        }
+        if self.ball_speed <= 0.0 || self.paddle_speed <= 0.0 {
+            return Err("ball and paddle speeds must be positive".into());
+        }
+        if self.speed_up_per_hit < 0.0 {
+            return Err("the speed-up per hit can't be negative".into());
+        }
+        if self.max_ball_speed < self.ball_speed {
+            return Err("the maximum ball speed can't be below the ball speed".into());
+        }
+        if !(0.0..80.0).contains(&self.max_bounce_angle)
+            || !(0.0..80.0).contains(&self.max_serve_angle)
+        {
+            return Err("bounce and serve angles must be between 0 and 80 degrees".into());
+        }
+        if self.paddle_spin < 0.0 {
+            return Err("the paddle spin can't be negative".into());
+        }
+        if self.serve_delay_secs < 0.0 {
+            return Err("the serve delay can't be negative".into());
+        }
+        if self.reconnect_grace_secs < 0.0 {
+            return Err("the reconnect grace period can't be negative".into());
+        }
+        if self.paddle_size.min_element() <= 0.0 || self.ball_size <= 0.0 {
+            return Err("paddle and ball sizes must be positive".into());
+        }
+        if self.paddle_size.y > self.field_size.y || self.ball_size > self.field_size.y {
+            return Err("the paddle and the ball must fit into the field height".into());
+        }
+        if self.field_size.x <= 2.0 * self.paddle_size.x + self.ball_size {
+            return Err("the field is too narrow for two paddles and the ball".into());
+        }
+
+        Ok(())
+    }
+
+    /// Horizontal distance of the paddles from the center of the field.
+    pub fn paddle_x(&self) -> f32 {
+        self.field_size.x / 2.0 - self.paddle_size.x / 2.0
+    }
+
+    /// Highest vertical position of a paddle center.
+    pub fn paddle_max_y(&self) -> f32 {
+        self.field_size.y / 2.0 - self.paddle_size.y / 2.0
+    }
+
+    /// Moves a paddle by one input held for `dt` seconds, the duration of a simulation tick.
+    pub fn move_paddle(&self, position: Vec2, direction: Vec2, dt: f32) -> Vec2 {
+        let paddle_max_y = self.paddle_max_y();
+        let mut position = position + direction * self.paddle_speed * dt;
+        position.y = position.y.clamp(-paddle_max_y, paddle_max_y);
+        position
+    }
+
+    /// Highest position of the ball center on each axis.
+    pub fn ball_max(&self) -> Vec2 {
+        self.field_size / 2.0 - self.ball_size / 2.0
+    }
+
+    /// Velocity of the ball at the start of a round.
+    ///
+    /// The ball is served at a random angle towards the side of `direction`.
+    pub fn serve_velocity(&self, rng: &mut impl Rng, direction: f32) -> Vec2 {
+        let max_angle = self.max_serve_angle.to_radians();
+        let angle = rng.gen_range(-max_angle..=max_angle);
+        Vec2::new(angle.cos() * direction.signum(), angle.sin()) * self.ball_speed
+    }
+
+    /// Velocity of the ball after bouncing off a paddle.
+    ///
+    /// `velocity` is the velocity reflected at the paddle, `offset` where the ball hit the paddle
+    /// from -1 at its bottom to 1 at its top edge and `paddle_velocity` the vertical velocity of
+    /// the paddle.
+    pub fn paddle_bounce(&self, velocity: Vec2, offset: f32, paddle_velocity: f32) -> Vec2 {
+        let speed = (velocity.length() * (1.0 + self.speed_up_per_hit)).min(self.max_ball_speed);
+        let max_angle = self.max_bounce_angle.to_radians();
+        let direction = velocity.x.signum();
+
+        let angle = offset.clamp(-1.0, 1.0) * max_angle;
+        let spun = Vec2::new(angle.cos(), angle.sin()) * speed
+            + Vec2::Y * paddle_velocity * self.paddle_spin;
+        let angle = spun.y.atan2(spun.x).clamp(-max_angle, max_angle);
+
+        Vec2::new(angle.cos() * direction, angle.sin()) * speed
+    }
+
+    /// Returns whether a match with the given scores after `round` rounds is over.
+    pub fn is_match_over(&self, score1: u16, score2: u16, round: u16) -> bool {
+        let reached = match self.win_condition {
+            WinCondition::Rounds(rounds) => round >= rounds,
+            WinCondition::TargetScore(target) => score1.max(score2) >= target,
+        };
+        reached && (!self.win_by_two || score1.abs_diff(score2) >= 2)
+    }
+
+    /// Short description of the match progress for the HUD.
+    pub fn progress_label(&self, round: u16) -> String {
+        match self.win_condition {
+            WinCondition::Rounds(rounds) => format!("Round: {round}/{rounds}"),
+            WinCondition::TargetScore(target) => format!("Round: {round}, first to {target}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Writes a rules file for a test into the temporary directory.
+    fn rules_file(test: &str, text: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("ping-pong-{test}-{}.ron", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn default_rules_round_trip() {
+        let rules = MatchRules::default();
+        assert_eq!(rules.validate(), Ok(()));
+
+        let path = rules_file("round-trip", &ron::to_string(&rules).unwrap());
+        let loaded = MatchRules::load(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded, rules);
+    }
+
+    #[test]
+    fn partial_rules_keep_defaults() {
+        let path = rules_file(
+            "partial",
+            "(win_condition: TargetScore(11), win_by_two: true, field_size: (1600.0, 900.0))",
+        );
+        let loaded = MatchRules::load(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            loaded,
+            MatchRules {
+                win_condition: WinCondition::TargetScore(11),
+                win_by_two: true,
+                field_size: Vec2::new(1600.0, 900.0),
+                ..Default::default()
+            }
+        );
+        assert_eq!(loaded.validate(), Ok(()));
+    }
+
+    #[test]
+    fn malformed_rules_file_is_an_error() {
+        let path = rules_file("malformed", "(ball_speed: fast)");
+        let loaded = MatchRules::load(&path);
+        fs::remove_file(path).unwrap();
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let invalid: [fn(&mut MatchRules); 14] = [
+            |rules| rules.win_condition = WinCondition::Rounds(0),
+            |rules| rules.win_condition = WinCondition::TargetScore(0),
+            |rules| rules.ball_speed = f32::NAN,
+            |rules| rules.max_ball_speed = f32::INFINITY,
+            |rules| rules.field_size.x = f32::NEG_INFINITY,
+            |rules| rules.ball_speed = 0.0,
+            |rules| rules.paddle_speed = -150.0,
+            |rules| rules.speed_up_per_hit = -0.1,
+            |rules| rules.max_ball_speed = rules.ball_speed / 2.0,
+            |rules| rules.max_bounce_angle = 90.0,
+            |rules| rules.serve_delay_secs = -1.0,
+            |rules| rules.paddle_size.x = 0.0,
+            |rules| rules.ball_size = rules.field_size.y + 1.0,
+            |rules| rules.field_size.x = 2.0 * rules.paddle_size.x + rules.ball_size,
+        ];
+        for (index, invalidate) in invalid.into_iter().enumerate() {
+            let mut rules = MatchRules::default();
+            invalidate(&mut rules);
+            assert!(rules.validate().is_err(), "case {index}: {rules:?}");
+        }
+    }
+
+    /// Angle of a velocity from the horizontal, in degrees.
+    fn angle_of(velocity: Vec2) -> f32 {
+        velocity.y.atan2(velocity.x.abs()).to_degrees()
+    }
+
+    #[test]
+    fn center_hit_bounces_back_horizontally() {
+        let rules = MatchRules::default();
+
+        let velocity = rules.paddle_bounce(Vec2::new(-200.0, 0.0), 0.0, 0.0);
+        assert!(velocity.x < 0.0);
+        assert_eq!(velocity.y, 0.0);
+        assert!((velocity.length() - 200.0 * (1.0 + rules.speed_up_per_hit)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn edge_hit_bounces_at_max_angle() {
+        let rules = MatchRules::default();
+
+        for (offset, expected) in [(1.0, 60.0), (3.0, 60.0), (-1.0, -60.0), (-3.0, -60.0)] {
+            let velocity = rules.paddle_bounce(Vec2::new(200.0, 0.0), offset, 0.0);
+            assert!(velocity.x > 0.0);
+            assert!((angle_of(velocity) - expected).abs() < 1e-3);
+        }
+
+        // Spin doesn't push the ball beyond the maximum angle either.
+        let velocity = rules.paddle_bounce(Vec2::new(200.0, 0.0), 1.0, 1000.0);
+        assert!((angle_of(velocity) - rules.max_bounce_angle).abs() < 1e-3);
+    }
+
+    #[test]
+    fn spin_follows_paddle_movement() {
+        let rules = MatchRules::default();
+
+        let up = rules.paddle_bounce(Vec2::new(200.0, 0.0), 0.0, 100.0);
+        assert!(up.y > 0.0);
+        let down = rules.paddle_bounce(Vec2::new(200.0, 0.0), 0.0, -100.0);
+        assert!(down.y < 0.0);
+        assert_eq!(up.length(), down.length());
+    }
+
+    #[test]
+    fn bounce_never_exceeds_max_speed() {
+        let rules = MatchRules::default();
+
+        for offset in [-1.0, -0.5, 0.0, 0.5, 1.0] {
+            for paddle_velocity in [-rules.paddle_speed, 0.0, rules.paddle_speed] {
+                let velocity = Vec2::new(rules.max_ball_speed, 0.0);
+                let velocity = rules.paddle_bounce(velocity, offset, paddle_velocity);
+                assert!(velocity.length() <= rules.max_ball_speed + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn serve_goes_towards_direction() {
+        let rules = MatchRules::default();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let right = rules.serve_velocity(&mut rng, 1.0);
+            assert!(right.x > 0.0);
+            assert!(angle_of(right).abs() <= rules.max_serve_angle + 1e-3);
+            assert!((right.length() - rules.ball_speed).abs() < 1e-3);
+
+            let left = rules.serve_velocity(&mut rng, -1.0);
+            assert!(left.x < 0.0);
+            assert!(angle_of(left).abs() <= rules.max_serve_angle + 1e-3);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Scoring and serves.
+//!
+//! The match entity of every room carries its [`Score`] and next [`Serve`]. Both are only
+//! changed by the server and replicated to the clients, so everybody shows the same score.
+//!
+//! A point goes to the opponent of the [`Side`] whose goal line the ball crossed. The ball is
+//! then put back into the center and served towards the player who lost the point once the
+//! serve delay of the [`MatchRules`] is over. When a paused match resumes, the ball waits for the
+//! serve delay again before it continues its flight.
+
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{rules::MatchRules, tick::TickRate};
+
+/// Side of the field, the first player's paddle is on the left.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn opponent(self) -> Self {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+
+    /// Sign of the horizontal direction towards this side.
+    pub fn direction(self) -> f32 {
+        match self {
+            Side::Left => -1.0,
+            Side::Right => 1.0,
+        }
+    }
+
+    /// Number of the player on this side, as used in the messages.
+    pub fn actor(self) -> u64 {
+        match self {
+            Side::Left => 1,
+            Side::Right => 2,
+        }
+    }
+}
+
+/// Returns the side whose goal line the ball at `ball_x` crossed, if any.
+pub fn conceding_side(ball_x: f32, rules: &MatchRules) -> Option<Side> {
+    let ball_max_x = rules.ball_max().x;
+    if ball_x <= -ball_max_x {
+        Some(Side::Left)
+    } else if ball_x >= ball_max_x {
+        Some(Side::Right)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Score {
+    pub left: u16,
+    pub right: u16,
+    /// Rounds played so far.
+    pub round: u16,
+}
+
+impl Score {
+    pub fn of(&self, side: Side) -> u16 {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    /// Ends the round with the ball in the goal of `conceding` and returns the side that scored.
+    pub fn award_point(&mut self, conceding: Side) -> Side {
+        let scorer = conceding.opponent();
+        match scorer {
+            Side::Left => self.left += 1,
+            Side::Right => self.right += 1,
+        }
+        self.round += 1;
+
+        scorer
+    }
+
+    pub fn is_match_over(&self, rules: &MatchRules) -> bool {
+        rules.is_match_over(self.left, self.right, self.round)
+    }
+
+    /// Returns the side with more points, `None` while they are level.
+    pub fn leader(&self) -> Option<Side> {
+        match self.left.cmp(&self.right) {
+            Ordering::Greater => Some(Side::Left),
+            Ordering::Less => Some(Side::Right),
+            Ordering::Equal => None,
+        }
+    }
+}
+
+/// Server tick at which the ball leaves the center, it waits there until then.
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Serve {
+    pub tick: u32,
+}
+
+impl Serve {
+    /// A serve after the serve delay of the rules, counted from `tick`.
+    pub fn after(tick: u32, rules: &MatchRules, tick_rate: TickRate) -> Self {
+        let delay = (rules.serve_delay_secs * tick_rate.0 as f32).round() as u32;
+        Self { tick: tick + delay }
+    }
+
+    pub fn is_due(&self, tick: u32) -> bool {
+        tick >= self.tick
+    }
+
+    /// Whole seconds left until the serve at `tick`, `None` once the ball is in play.
+    pub fn countdown(&self, tick: u32, tick_rate: TickRate) -> Option<u32> {
+        if self.is_due(tick) {
+            return None;
+        }
+        Some((self.tick - tick).div_ceil(tick_rate.0.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn ball_in_left_goal_scores_for_right() {
+        let rules = MatchRules::default();
+        let mut score = Score::default();
+
+        let conceding = conceding_side(-rules.ball_max().x - 1.0, &rules).unwrap();
+        assert_eq!(conceding, Side::Left);
+        assert_eq!(score.award_point(conceding), Side::Right);
+        assert_eq!(
+            score,
+            Score {
+                left: 0,
+                right: 1,
+                round: 1
+            }
+        );
+    }
+
+    #[test]
+    fn ball_in_right_goal_scores_for_left() {
+        let rules = MatchRules::default();
+        let mut score = Score::default();
+
+        let conceding = conceding_side(rules.ball_max().x, &rules).unwrap();
+        assert_eq!(conceding, Side::Right);
+        assert_eq!(score.award_point(conceding), Side::Left);
+        assert_eq!(
+            score,
+            Score {
+                left: 1,
+                right: 0,
+                round: 1
+            }
+        );
+    }
+
+    #[test]
+    fn ball_in_field_scores_nothing() {
+        let rules = MatchRules::default();
+        assert_eq!(conceding_side(0.0, &rules), None);
+        assert_eq!(conceding_side(rules.ball_max().x - 1.0, &rules), None);
+        assert_eq!(conceding_side(1.0 - rules.ball_max().x, &rules), None);
+    }
+
+    #[test]
+    fn serve_goes_to_player_who_lost_the_point() {
+        let rules = MatchRules::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            assert!(rules.serve_velocity(&mut rng, Side::Left.direction()).x < 0.0);
+            assert!(rules.serve_velocity(&mut rng, Side::Right.direction()).x > 0.0);
+        }
+    }
+
+    #[test]
+    fn serve_counts_down_whole_seconds() {
+        let rules = MatchRules {
+            serve_delay_secs: 2.0,
+            ..Default::default()
+        };
+        let tick_rate = TickRate(60);
+        let serve = Serve::after(100, &rules, tick_rate);
+
+        assert_eq!(serve.tick, 220);
+        assert_eq!(serve.countdown(100, tick_rate), Some(2));
+        assert_eq!(serve.countdown(160, tick_rate), Some(1));
+        assert_eq!(serve.countdown(219, tick_rate), Some(1));
+        assert_eq!(serve.countdown(220, tick_rate), None);
+        assert!(serve.is_due(220));
+    }
+}
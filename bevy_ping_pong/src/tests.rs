@@ -0,0 +1,1451 @@
+//! Headless tests of a server and two clients connected over loopback UDP.
+//!
+//! All apps advance their clocks by exactly one server tick per update, so the simulation steps
+//! the same way in every run. Only the packet delivery depends on the operating system, which is
+//! why the tests wait for conditions with [`TestMatch::run_until`] instead of counting frames.
+
+use std::{
+    net::UdpSocket,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{
+    asset::AssetPlugin, gizmos::GizmoPlugin, input::InputPlugin, render::render_resource::Shader,
+    time::TimeUpdateStrategy,
+};
+use bevy_replicon::{
+    renet::{
+        transport::{
+            ClientAuthentication, NetcodeClientTransport, ServerAuthentication, ServerConfig,
+        },
+        ConnectionConfig,
+    },
+    replicon_core::NetworkChannels,
+    server::TickPolicy,
+};
+
+use super::*;
+use crate::rules::WinCondition;
+use crate::{
+    autopilot::{AutoPilotPlugin, AutoPilotStats},
+    bot::{BotDifficulty, FIRST_BOT_ID},
+    hot_seat::{HotSeatPlugin, GUEST_ID},
+    physics::predict_crossing,
+    playback::{ReplayPlayback, ReplayPlaybackPlugin},
+    replay::{self, Replay, ReplayRecorder},
+    room::RoomMessage,
+    validation::{ValidationSettings, Violations},
+    verify::verify,
+};
+
+/// Updates after which a condition is considered to never become true.
+const MAX_UPDATES: usize = 600;
+
+fn current_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+}
+
+fn connection_config(app: &App) -> ConnectionConfig {
+    let network_channels = app.world.resource::<NetworkChannels>();
+    ConnectionConfig {
+        server_channels_config: network_channels.get_server_configs(),
+        client_channels_config: network_channels.get_client_configs(),
+        ..Default::default()
+    }
+}
+
+/// Advances the clock of an app by one tick on every update.
+fn step_time(app: &mut App, tick_rate: TickRate) {
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        tick_rate.duration(),
+    )));
+}
+
+fn server_app(rules: MatchRules) -> (App, u16) {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        ReplicationPlugins.build().set(ServerPlugin {
+            tick_policy: TickPolicy::Manual,
+            visibility_policy: VisibilityPolicy::Whitelist,
+            ..Default::default()
+        }),
+        PingPongSharedPlugin,
+        PingPongServerPlugin,
+    ))
+    .insert_resource(rules)
+    .init_resource::<Rooms>();
+    step_time(&mut app, TickRate::default());
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_addr = socket.local_addr().unwrap();
+    let transport = NetcodeServerTransport::new(
+        ServerConfig {
+            current_time: current_time(),
+            max_clients: 4,
+            protocol_id: PROTOCOL_ID,
+            authentication: ServerAuthentication::Unsecure,
+            public_addresses: vec![server_addr],
+        },
+        socket,
+    )
+    .unwrap();
+    let server = RenetServer::new(connection_config(&app));
+    app.insert_resource(server).insert_resource(transport);
+
+    (app, server_addr.port())
+}
+
+/// A server that also runs the client of its host player.
+fn host_app(rules: MatchRules) -> (App, u16) {
+    let (mut app, port) = server_app(rules);
+    add_client_plugins(&mut app);
+    app.insert_resource(HostPlayer {
+        name: "Host".to_string(),
+    })
+    .insert_resource(LocalData {
+        client_id: SERVER_ID.raw(),
+    });
+
+    (app, port)
+}
+
+fn client_app(port: u16, client_id: u64) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, ReplicationPlugins, PingPongSharedPlugin));
+    add_client_plugins(&mut app);
+    step_time(&mut app, TickRate::default());
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let transport = NetcodeClientTransport::new(
+        current_time(),
+        ClientAuthentication::Unsecure {
+            client_id,
+            protocol_id: PROTOCOL_ID,
+            server_addr: format!("127.0.0.1:{port}").parse().unwrap(),
+            user_data: Some(name_to_user_data(&format!("Player {client_id}"))),
+        },
+        socket,
+    )
+    .unwrap();
+    let client = RenetClient::new(connection_config(&app));
+    app.insert_resource(client)
+        .insert_resource(transport)
+        .insert_resource(LocalData { client_id });
+
+    app
+}
+
+/// A hot-seat game without any network resources.
+fn offline_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        ReplicationPlugins.build().set(ServerPlugin {
+            tick_policy: TickPolicy::Manual,
+            visibility_policy: VisibilityPolicy::Whitelist,
+            ..Default::default()
+        }),
+        PingPongSharedPlugin,
+        PingPongServerPlugin,
+    ));
+    add_client_plugins(&mut app);
+    app.add_plugins(HotSeatPlugin);
+    step_time(&mut app, TickRate::default());
+
+    app
+}
+
+/// Adds the client plugin and what it needs to run without a window.
+fn add_client_plugins(app: &mut App) {
+    app.add_plugins((AssetPlugin::default(), InputPlugin))
+        .init_asset::<Shader>()
+        .add_plugins((GizmoPlugin, PingPongClientPlugin));
+}
+
+/// A server with its connected clients.
+struct TestMatch {
+    server: App,
+    clients: Vec<App>,
+}
+
+impl TestMatch {
+    const CLIENT_IDS: [u64; 2] = [1, 2];
+
+    /// Connects both clients and waits until they are in the lobby.
+    fn connect(rules: MatchRules) -> Self {
+        let (server, port) = server_app(rules);
+        let clients = Self::CLIENT_IDS
+            .into_iter()
+            .map(|client_id| client_app(port, client_id))
+            .collect();
+        let mut test_match = Self { server, clients };
+        test_match.run_until("both clients entered the lobby", |test_match| {
+            let lobby = test_match.server.world.resource::<Lobby>();
+            Self::CLIENT_IDS
+                .iter()
+                .all(|&client_id| lobby.contains(client_id))
+        });
+
+        test_match
+    }
+
+    /// Connects both clients and pairs them for a match.
+    fn start(rules: MatchRules) -> Self {
+        let mut test_match = Self::connect(rules);
+        test_match.ready();
+
+        test_match
+    }
+
+    /// Readies both clients in the lobby and waits until their match started.
+    fn ready(&mut self) {
+        for client in &mut self.clients {
+            client.world.send_event(ClientMessage {
+                msg: C2sMessage::SetReady(true),
+            });
+        }
+        self.run_until("the match started", |test_match| {
+            test_match
+                .clients
+                .iter()
+                .all(|client| game_state(client) == GameState::Game)
+        });
+    }
+
+    /// Connects another client and waits until it's in the lobby.
+    fn add_client(&mut self, client_id: u64) {
+        let port = self
+            .server
+            .world
+            .resource::<NetcodeServerTransport>()
+            .addresses()[0]
+            .port();
+        self.clients.push(client_app(port, client_id));
+        self.run_until("the client entered the lobby", |test_match| {
+            test_match
+                .server
+                .world
+                .resource::<Lobby>()
+                .contains(client_id)
+        });
+    }
+
+    fn update(&mut self) {
+        self.server.update();
+        for client in &mut self.clients {
+            client.update();
+        }
+        // Give the loopback interface a moment to deliver the packets.
+        std::thread::sleep(Duration::from_micros(200));
+    }
+
+    /// Updates all apps until `condition` holds, panics if it doesn't within a few seconds.
+    fn run_until(&mut self, description: &str, condition: impl FnMut(&mut Self) -> bool) {
+        self.run_until_within(MAX_UPDATES, description, condition);
+    }
+
+    /// Like [`TestMatch::run_until`], but waits up to `updates` updates.
+    fn run_until_within(
+        &mut self,
+        updates: usize,
+        description: &str,
+        mut condition: impl FnMut(&mut Self) -> bool,
+    ) {
+        for _ in 0..updates {
+            if condition(self) {
+                return;
+            }
+            self.update();
+        }
+        panic!("timed out waiting until {description}");
+    }
+
+    /// Plays a match of a single round, in which the right player scores the only point.
+    fn end() -> Self {
+        let rules = MatchRules {
+            win_condition: WinCondition::Rounds(1),
+            serve_delay_secs: 0.0,
+            ..Default::default()
+        };
+        let mut test_match = Self::start(rules.clone());
+
+        // Send the ball into the goal behind the left paddle.
+        let room_id = test_match.room_id();
+        let world = &mut test_match.server.world;
+        let mut balls =
+            world.query_filtered::<(&RoomId, &mut PlayerPosition, &mut PlayerSpeed), With<Ball>>();
+        for (_, mut position, mut velocity) in balls
+            .iter_mut(world)
+            .filter(|(ball_room_id, ..)| **ball_room_id == room_id)
+        {
+            **position = Vec2::new(1.0 - rules.ball_max().x, rules.ball_max().y);
+            **velocity = Vec2::new(-rules.ball_speed, 0.0);
+        }
+
+        test_match.run_until("the match ended", |test_match| {
+            test_match
+                .clients
+                .iter()
+                .all(|client| game_state(client) == GameState::End)
+        });
+
+        test_match
+    }
+
+    fn room_id(&self) -> RoomId {
+        let client_id = Self::CLIENT_IDS[0];
+        self.server
+            .world
+            .resource::<Rooms>()
+            .room_of(client_id)
+            .expect("client should be in a room")
+    }
+
+    /// Returns whether the ball in the room of the first client left the center.
+    fn serve_is_due(&mut self) -> bool {
+        let room_id = self.room_id();
+        let world = &mut self.server.world;
+        let tick = world.resource::<RepliconTick>().get();
+        world
+            .query::<(&RoomId, &Serve)>()
+            .iter(world)
+            .find(|(match_room_id, _)| **match_room_id == room_id)
+            .is_some_and(|(_, serve)| serve.is_due(tick))
+    }
+
+    /// Server state of the room of the first client.
+    fn room_game_data(&self) -> &GameData {
+        let rooms = self.server.world.resource::<Rooms>();
+        &rooms.get(self.room_id()).unwrap().game_data
+    }
+}
+
+/// Updates a hot-seat app until it's in `state`, panics if it doesn't get there.
+fn run_offline_until(app: &mut App, state: GameState) {
+    for _ in 0..MAX_UPDATES {
+        if game_state(app) == state {
+            return;
+        }
+        app.update();
+    }
+    panic!("timed out waiting for {state:?}");
+}
+
+fn game_state(app: &App) -> GameState {
+    *app.world.resource::<State<GameState>>().get()
+}
+
+fn game_data(app: &App) -> &GameData {
+    app.world.resource::<GameData>()
+}
+
+/// Replicated position of the paddle of `client_id`.
+fn paddle_position(app: &mut App, client_id: u64) -> Option<Vec2> {
+    app.world
+        .query_filtered::<(&Player, &PlayerPosition), With<Paddle>>()
+        .iter(&app.world)
+        .find(|(player, _)| player.0.raw() == client_id)
+        .map(|(_, position)| **position)
+}
+
+/// Predicted position of the local paddle of a client.
+fn predicted_position(app: &mut App) -> Option<Vec2> {
+    app.world
+        .query::<&PredictedPosition>()
+        .iter(&app.world)
+        .next()
+        .map(|position| **position)
+}
+
+/// Rooms of the balls, paddles and match entities an app knows about.
+fn entity_rooms(app: &mut App) -> Vec<RoomId> {
+    app.world
+        .query_filtered::<&RoomId, Or<(With<Ball>, With<Paddle>, With<Score>)>>()
+        .iter(&app.world)
+        .copied()
+        .collect()
+}
+
+fn replicated_score(app: &mut App) -> Option<Score> {
+    let current_room = app.world.resource::<CurrentRoom>().0;
+    app.world
+        .query::<(&RoomId, &Score)>()
+        .iter(&app.world)
+        .find(|(room_id, _)| current_room == Some(**room_id))
+        .map(|(_, score)| *score)
+}
+
+/// An empty directory for the replays of a test.
+fn replay_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ping-pong-{test}-{}", std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    replay::create_replay_dir(&dir).unwrap();
+
+    dir
+}
+
+fn replay_files(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect()
+}
+
+#[test]
+fn ready_clients_are_paired() {
+    let test_match = TestMatch::start(MatchRules::default());
+
+    let room_id = test_match.room_id();
+    for client in &test_match.clients {
+        assert_eq!(client.world.resource::<CurrentRoom>().0, Some(room_id));
+        let game_data = game_data(client);
+        let mut actors = [game_data.actor1, game_data.actor2];
+        actors.sort();
+        assert_eq!(actors, TestMatch::CLIENT_IDS);
+    }
+}
+
+#[test]
+fn accepted_challenge_starts_a_match_of_the_pair() {
+    let mut test_match = TestMatch::connect(MatchRules::default());
+    test_match.add_client(3);
+    let [challenger, challenged] = TestMatch::CLIENT_IDS;
+
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::Challenge(challenged),
+    });
+    test_match.run_until("the challenge arrived", |test_match| {
+        test_match.clients[1]
+            .world
+            .resource::<Lobby>()
+            .has_challenge(challenger, challenged)
+    });
+    test_match.clients[1].world.send_event(ClientMessage {
+        msg: C2sMessage::AcceptChallenge(challenger),
+    });
+    test_match.run_until("the match of the pair started", |test_match| {
+        test_match.clients[..2]
+            .iter()
+            .all(|client| game_state(client) == GameState::Game)
+    });
+
+    let room_id = test_match.room_id();
+    let rooms = test_match.server.world.resource::<Rooms>();
+    assert_eq!(rooms.room_of(challenged), Some(room_id));
+    assert_eq!(rooms.room_of(3), None);
+    assert!(test_match.server.world.resource::<Lobby>().contains(3));
+    assert_eq!(game_state(&test_match.clients[2]), GameState::Menu);
+    let game_data = game_data(&test_match.clients[0]);
+    assert_eq!(
+        (game_data.actor1, game_data.actor2),
+        (challenger, challenged)
+    );
+}
+
+#[test]
+fn declined_challenge_leaves_both_in_the_lobby() {
+    let mut test_match = TestMatch::connect(MatchRules::default());
+    let [challenger, challenged] = TestMatch::CLIENT_IDS;
+
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::Challenge(challenged),
+    });
+    test_match.run_until("the challenge arrived", |test_match| {
+        test_match.clients.iter().all(|client| {
+            client
+                .world
+                .resource::<Lobby>()
+                .has_challenge(challenger, challenged)
+        })
+    });
+    test_match.clients[1].world.send_event(ClientMessage {
+        msg: C2sMessage::DeclineChallenge(challenger),
+    });
+    test_match.run_until("the challenger was told", |test_match| {
+        !test_match.clients[0]
+            .world
+            .resource::<Lobby>()
+            .has_challenge(challenger, challenged)
+    });
+
+    let lobby = test_match.server.world.resource::<Lobby>();
+    assert!(!lobby.has_challenge(challenger, challenged));
+    for client_id in TestMatch::CLIENT_IDS {
+        assert!(lobby.contains(client_id));
+        assert!(!lobby.is_paired(client_id));
+    }
+    assert!(test_match.server.world.resource::<Rooms>().is_empty());
+    assert!(test_match
+        .clients
+        .iter()
+        .all(|client| game_state(client) == GameState::Menu));
+}
+
+#[test]
+fn busy_players_cant_be_challenged() {
+    // The first two clients take the only room.
+    let mut test_match = TestMatch::start(MatchRules::default());
+    let [playing_id, _] = TestMatch::CLIENT_IDS;
+    let (waiting_id, challenger) = (3, 4);
+    test_match.add_client(waiting_id);
+    test_match.add_client(challenger);
+
+    // Paired with a bot, but waiting for a free room.
+    test_match.clients[2].world.send_event(ClientMessage {
+        msg: C2sMessage::PlayBot(BotDifficulty::Easy),
+    });
+    test_match.run_until("the bot match is waiting", |test_match| {
+        test_match
+            .server
+            .world
+            .resource::<Lobby>()
+            .is_paired(waiting_id)
+    });
+
+    for challenged in [waiting_id, playing_id] {
+        test_match.clients[3].world.send_event(ClientMessage {
+            msg: C2sMessage::Challenge(challenged),
+        });
+    }
+    // Messages arrive in order, so the challenges were handled once the challenger is ready.
+    test_match.clients[3].world.send_event(ClientMessage {
+        msg: C2sMessage::SetReady(true),
+    });
+    test_match.run_until("the challenger is ready", |test_match| {
+        test_match.clients[3]
+            .world
+            .resource::<Lobby>()
+            .member(challenger)
+            .is_some_and(|member| member.ready)
+    });
+
+    for lobby in [
+        test_match.server.world.resource::<Lobby>(),
+        test_match.clients[3].world.resource::<Lobby>(),
+    ] {
+        assert!(!lobby.has_challenge(challenger, waiting_id));
+        assert!(!lobby.has_challenge(challenger, playing_id));
+    }
+}
+
+#[test]
+fn clients_only_receive_the_entities_of_their_room() {
+    let (mut server, port) = server_app(MatchRules::default());
+    server.insert_resource(Rooms::new(2));
+    let client_ids = [1, 2, 3, 4];
+    let mut test_match = TestMatch {
+        server,
+        clients: client_ids
+            .into_iter()
+            .map(|client_id| client_app(port, client_id))
+            .collect(),
+    };
+    test_match.run_until("all clients entered the lobby", |test_match| {
+        let lobby = test_match.server.world.resource::<Lobby>();
+        client_ids
+            .iter()
+            .all(|&client_id| lobby.contains(client_id))
+    });
+    for pair in [0..2, 2..4] {
+        for client in &mut test_match.clients[pair.clone()] {
+            client.world.send_event(ClientMessage {
+                msg: C2sMessage::SetReady(true),
+            });
+        }
+        test_match.run_until("the match of the pair started", |test_match| {
+            test_match.clients[pair.clone()]
+                .iter()
+                .all(|client| game_state(client) == GameState::Game)
+        });
+    }
+
+    let rooms = test_match.server.world.resource::<Rooms>();
+    let room_ids = client_ids.map(|client_id| rooms.room_of(client_id).unwrap());
+    assert_eq!(room_ids[0], room_ids[1]);
+    assert_eq!(room_ids[2], room_ids[3]);
+    assert_ne!(room_ids[0], room_ids[2]);
+    for _ in 0..60 {
+        test_match.update();
+        for (client, room_id) in test_match.clients.iter_mut().zip(room_ids) {
+            assert!(entity_rooms(client).iter().all(|&id| id == room_id));
+        }
+    }
+    for client in &mut test_match.clients {
+        let balls = client
+            .world
+            .query_filtered::<(), With<Ball>>()
+            .iter(&client.world)
+            .count();
+        let paddles = client
+            .world
+            .query_filtered::<(), With<Paddle>>()
+            .iter(&client.world)
+            .count();
+        assert_eq!((balls, paddles), (1, 2));
+    }
+}
+
+#[test]
+fn inputs_move_the_paddle_on_every_client() {
+    let rules = MatchRules::default();
+    let mut test_match = TestMatch::start(rules.clone());
+    let client_id = TestMatch::CLIENT_IDS[0];
+    test_match.run_until("the paddle is replicated", |test_match| {
+        paddle_position(&mut test_match.clients[1], client_id).is_some()
+    });
+    let start = paddle_position(&mut test_match.clients[1], client_id).unwrap();
+
+    const INPUTS: u32 = 10;
+    for sequence in 1..=INPUTS {
+        test_match.clients[0].world.send_event(MoveDirection {
+            direction: Vec2::Y,
+            sequence,
+        });
+        test_match.update();
+    }
+
+    let expected = start.y + INPUTS as f32 * rules.paddle_speed * TickRate::default().duration();
+    test_match.run_until("the moved paddle is replicated", |test_match| {
+        paddle_position(&mut test_match.clients[1], client_id)
+            .is_some_and(|position| (position.y - expected).abs() < 0.01)
+    });
+    let server_position = paddle_position(&mut test_match.server, client_id).unwrap();
+    assert!((server_position.y - expected).abs() < 0.01);
+}
+
+#[test]
+fn predicted_paddle_is_reconciled_with_the_server() {
+    let mut test_match = TestMatch::start(MatchRules::default());
+    let client_id = TestMatch::CLIENT_IDS[0];
+    test_match.run_until("the paddle is predicted", |test_match| {
+        predicted_position(&mut test_match.clients[0]).is_some()
+    });
+    let start = predicted_position(&mut test_match.clients[0]).unwrap();
+
+    test_match.clients[0]
+        .world
+        .resource_mut::<Input<KeyCode>>()
+        .press(KeyCode::Up);
+    for _ in 0..10 {
+        test_match.update();
+        // Unacknowledged inputs keep the prediction ahead of the last update.
+        let client = &mut test_match.clients[0];
+        let predicted = predicted_position(client).unwrap();
+        let authoritative = paddle_position(client, client_id).unwrap();
+        assert!(predicted.y >= authoritative.y);
+    }
+    test_match.clients[0]
+        .world
+        .resource_mut::<Input<KeyCode>>()
+        .release(KeyCode::Up);
+
+    test_match.run_until("all inputs are acknowledged", |test_match| {
+        let server_position = paddle_position(&mut test_match.server, client_id).unwrap();
+        let client = &mut test_match.clients[0];
+        [
+            paddle_position(client, client_id),
+            predicted_position(client),
+        ]
+        .into_iter()
+        .all(|position| position.is_some_and(|position| position.distance(server_position) < 1e-4))
+    });
+    let predicted = predicted_position(&mut test_match.clients[0]).unwrap();
+    assert!(predicted.y > start.y);
+}
+
+#[test]
+fn point_is_scored_for_the_opponent_and_ends_the_match() {
+    let mut test_match = TestMatch::end();
+
+    for client in &mut test_match.clients {
+        let score = replicated_score(client).unwrap();
+        assert_eq!(
+            score,
+            Score {
+                left: 0,
+                right: 1,
+                round: 1
+            }
+        );
+        let game_data = game_data(client);
+        assert_eq!(game_data.winner(&score), Some(game_data.actor2));
+    }
+}
+
+#[test]
+fn rematch_starts_once_both_players_voted() {
+    let mut test_match = TestMatch::end();
+    let room_id = test_match.room_id();
+    let world = &mut test_match.server.world;
+    world.resource_mut::<MatchRules>().serve_delay_secs = 1.0;
+    let mut paddles = world.query_filtered::<(&RoomId, &mut PlayerPosition), With<Paddle>>();
+    for (_, mut position) in paddles
+        .iter_mut(world)
+        .filter(|(paddle_room_id, _)| **paddle_room_id == room_id)
+    {
+        position.y = 50.0;
+    }
+
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::Rematch,
+    });
+    test_match.run_until("the vote arrived", |test_match| {
+        !test_match.room_game_data().rematch_votes.is_empty()
+    });
+    for _ in 0..30 {
+        test_match.update();
+    }
+    assert_eq!(test_match.room_game_data().phase, GameState::End);
+    assert!(test_match
+        .clients
+        .iter()
+        .all(|client| game_state(client) == GameState::End));
+
+    test_match.clients[1].world.send_event(ClientMessage {
+        msg: C2sMessage::Rematch,
+    });
+    test_match.run_until("the rematch started", |test_match| {
+        test_match.room_game_data().phase == GameState::Game
+    });
+    assert!(test_match.room_game_data().rematch_votes.is_empty());
+    let world = &mut test_match.server.world;
+    let tick = world.resource::<RepliconTick>().get();
+    let (_, score, serve) = world
+        .query::<(&RoomId, &Score, &Serve)>()
+        .iter(world)
+        .find(|(match_room_id, ..)| **match_room_id == room_id)
+        .unwrap();
+    assert_eq!(*score, Score::default());
+    assert!(!serve.is_due(tick));
+    let mut balls = world.query_filtered::<(&RoomId, &PlayerPosition), With<Ball>>();
+    for (_, position) in balls
+        .iter(world)
+        .filter(|(ball_room_id, _)| **ball_room_id == room_id)
+    {
+        assert_eq!(**position, Vec2::ZERO);
+    }
+    let mut paddles = world.query_filtered::<(&RoomId, &PlayerPosition), With<Paddle>>();
+    for (_, position) in paddles
+        .iter(world)
+        .filter(|(paddle_room_id, _)| **paddle_room_id == room_id)
+    {
+        assert_eq!(position.y, 0.0);
+    }
+
+    test_match.run_until("both clients restarted", |test_match| {
+        test_match.clients.iter_mut().all(|client| {
+            game_state(client) == GameState::Game
+                && replicated_score(client) == Some(Score::default())
+        })
+    });
+}
+
+#[test]
+fn rematch_is_off_once_the_opponent_left() {
+    let mut test_match = TestMatch::end();
+    let [voting_id, leaving_id] = TestMatch::CLIENT_IDS;
+
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::Rematch,
+    });
+    test_match.run_until("the vote arrived", |test_match| {
+        test_match.room_game_data().rematch_votes == [voting_id]
+    });
+    test_match.clients[1].world.send_event(ClientMessage {
+        msg: C2sMessage::Leave,
+    });
+    test_match.run_until("the opponent is back in the lobby", |test_match| {
+        test_match
+            .server
+            .world
+            .resource::<Lobby>()
+            .contains(leaving_id)
+    });
+    assert_eq!(test_match.room_game_data().left, [leaving_id]);
+
+    // Voting again doesn't bring the match back either.
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::Rematch,
+    });
+    for _ in 0..30 {
+        test_match.update();
+    }
+    assert_eq!(test_match.room_game_data().phase, GameState::End);
+    assert_eq!(test_match.room_game_data().rematch_votes, [voting_id]);
+    assert_eq!(game_state(&test_match.clients[0]), GameState::End);
+}
+
+#[test]
+fn bot_moves_to_meet_the_ball() {
+    let rules = MatchRules {
+        serve_delay_secs: 0.0,
+        ..Default::default()
+    };
+    let mut test_match = TestMatch::connect(rules.clone());
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::PlayBot(BotDifficulty::Hard),
+    });
+    test_match.run_until("the match against the bot started", |test_match| {
+        game_state(&test_match.clients[0]) == GameState::Game
+    });
+    let bot_id = FIRST_BOT_ID;
+    assert_eq!(game_data(&test_match.clients[0]).actor2, bot_id);
+
+    // Send the ball towards the bot on the right.
+    let room_id = test_match.room_id();
+    let velocity = Vec2::new(300.0, 60.0);
+    let world = &mut test_match.server.world;
+    let mut balls =
+        world.query_filtered::<(&RoomId, &mut PlayerPosition, &mut PlayerSpeed), With<Ball>>();
+    for (_, mut position, mut ball_velocity) in balls
+        .iter_mut(world)
+        .filter(|(ball_room_id, ..)| **ball_room_id == room_id)
+    {
+        **position = Vec2::ZERO;
+        **ball_velocity = velocity;
+    }
+    let expected =
+        predict_crossing(Vec2::ZERO, velocity, rules.paddle_x(), rules.ball_max().y).unwrap();
+
+    for _ in 0..100 {
+        test_match.update();
+    }
+    let position = paddle_position(&mut test_match.server, bot_id).unwrap();
+    assert!(
+        (position.y - expected).abs() <= 2.0 * BotDifficulty::Hard.prediction_error(),
+        "bot at {} instead of {expected}",
+        position.y
+    );
+}
+
+#[test]
+fn disconnect_pauses_the_match_until_forfeit() {
+    let rules = MatchRules {
+        reconnect_grace_secs: 1.0,
+        serve_delay_secs: 0.5,
+        ..Default::default()
+    };
+    let mut test_match = TestMatch::start(rules);
+    let [leaving_id, staying_id] = TestMatch::CLIENT_IDS;
+    test_match.run_until("the ball is in play", |test_match| {
+        test_match.serve_is_due()
+    });
+
+    test_match.clients[0]
+        .world
+        .resource_mut::<NetcodeClientTransport>()
+        .disconnect();
+    test_match.run_until("the match is paused", |test_match| {
+        game_state(&test_match.clients[1]) == GameState::Paused
+    });
+    assert_eq!(game_data(&test_match.clients[1]).absent, [leaving_id]);
+
+    // Rejoining resumes the match, but the ball waits for a new countdown.
+    let port = test_match
+        .server
+        .world
+        .resource::<NetcodeServerTransport>()
+        .addresses()[0]
+        .port();
+    test_match.clients[0] = client_app(port, leaving_id);
+    test_match.run_until("the match resumed", |test_match| {
+        test_match
+            .clients
+            .iter()
+            .all(|client| game_state(client) == GameState::Game)
+    });
+    let room_id = test_match.room_id();
+    let ball_position = |test_match: &mut TestMatch| {
+        let world = &mut test_match.server.world;
+        world
+            .query_filtered::<(&RoomId, &PlayerPosition), With<Ball>>()
+            .iter(world)
+            .find(|(ball_room_id, _)| **ball_room_id == room_id)
+            .map(|(_, position)| **position)
+            .unwrap()
+    };
+    let resumed_at = ball_position(&mut test_match);
+    for _ in 0..10 {
+        test_match.update();
+    }
+    assert_eq!(ball_position(&mut test_match), resumed_at);
+    assert!(!test_match.serve_is_due());
+
+    test_match.clients[0]
+        .world
+        .resource_mut::<NetcodeClientTransport>()
+        .disconnect();
+    test_match.run_until("the match is paused again", |test_match| {
+        game_state(&test_match.clients[1]) == GameState::Paused
+    });
+    test_match.run_until("the match is forfeited", |test_match| {
+        game_state(&test_match.clients[1]) == GameState::End
+    });
+    let game_data = game_data(&test_match.clients[1]);
+    assert_eq!(game_data.forfeit_winner, Some(staying_id));
+    assert_eq!(game_data.left, [leaving_id]);
+    assert_eq!(
+        test_match
+            .server
+            .world
+            .resource::<Rooms>()
+            .room_of(leaving_id),
+        None
+    );
+}
+
+#[test]
+fn waiting_bot_leaves_with_its_opponent() {
+    // Both players take the only room, so a match against a bot has to wait in the lobby.
+    let mut test_match = TestMatch::start(MatchRules::default());
+    let client_id = 3;
+    test_match.add_client(client_id);
+    test_match.clients[2].world.send_event(ClientMessage {
+        msg: C2sMessage::PlayBot(BotDifficulty::Easy),
+    });
+    test_match.run_until("the client is paired with the bot", |test_match| {
+        let lobby = test_match.server.world.resource::<Lobby>();
+        lobby.is_paired(client_id) && lobby.is_paired(FIRST_BOT_ID)
+    });
+
+    test_match.clients[2]
+        .world
+        .resource_mut::<NetcodeClientTransport>()
+        .disconnect();
+    test_match.run_until("the bot left the lobby", |test_match| {
+        !test_match
+            .server
+            .world
+            .resource::<Lobby>()
+            .contains(FIRST_BOT_ID)
+    });
+    assert!(test_match
+        .server
+        .world
+        .resource::<Rooms>()
+        .room_of(FIRST_BOT_ID)
+        .is_none());
+}
+
+#[test]
+fn match_ends_without_winner_when_both_players_disconnect() {
+    let rules = MatchRules {
+        reconnect_grace_secs: 1.0,
+        ..Default::default()
+    };
+    let mut test_match = TestMatch::start(rules);
+    let room_id = test_match.room_id();
+
+    // A spectator stays to see how the match ends.
+    let spectator_id = 3;
+    test_match.add_client(spectator_id);
+    test_match.clients[2].world.send_event(ClientMessage {
+        msg: C2sMessage::Watch(room_id.0),
+    });
+    test_match.run_until("the spectator watches the match", |test_match| {
+        game_state(&test_match.clients[2]) == GameState::Game
+    });
+
+    for client in &mut test_match.clients[..2] {
+        client
+            .world
+            .resource_mut::<NetcodeClientTransport>()
+            .disconnect();
+    }
+    test_match.run_until("the match ended", |test_match| {
+        game_state(&test_match.clients[2]) == GameState::End
+    });
+    let game_data = game_data(&test_match.clients[2]);
+    assert_eq!(game_data.forfeit_winner, None);
+    let mut left = game_data.left.clone();
+    left.sort();
+    assert_eq!(left, TestMatch::CLIENT_IDS);
+}
+
+#[test]
+fn client_starts_hosting_while_running() {
+    let (mut host, port) = host_app(MatchRules::default());
+    host.world.remove_resource::<Rooms>();
+    let host_id = SERVER_ID.raw();
+    for _ in 0..10 {
+        host.update();
+    }
+    // Without rooms the server plugin stays idle, like on the start screen.
+    assert!(!host.world.resource::<Lobby>().contains(host_id));
+    assert_eq!(
+        host.world
+            .query::<&SimulationTick>()
+            .iter(&host.world)
+            .count(),
+        0
+    );
+
+    host.insert_resource(Rooms::default());
+    let remote_id = TestMatch::CLIENT_IDS[0];
+    let mut test_match = TestMatch {
+        server: host,
+        clients: vec![client_app(port, remote_id)],
+    };
+    test_match.run_until(
+        "the remote client sees the host in the lobby",
+        |test_match| {
+            test_match.clients[0]
+                .world
+                .resource::<Lobby>()
+                .contains(host_id)
+        },
+    );
+}
+
+#[test]
+fn host_plays_against_a_remote_client() {
+    let rules = MatchRules::default();
+    let (host, port) = host_app(rules.clone());
+    let [remote_id, _] = TestMatch::CLIENT_IDS;
+    let host_id = SERVER_ID.raw();
+    let mut test_match = TestMatch {
+        server: host,
+        clients: vec![client_app(port, remote_id)],
+    };
+    test_match.run_until("both players entered the lobby", |test_match| {
+        let lobby = test_match.server.world.resource::<Lobby>();
+        lobby.contains(host_id) && lobby.contains(remote_id)
+    });
+
+    for app in [&mut test_match.server, &mut test_match.clients[0]] {
+        app.world.send_event(ClientMessage {
+            msg: C2sMessage::SetReady(true),
+        });
+    }
+    test_match.run_until("the match started", |test_match| {
+        game_state(&test_match.server) == GameState::Game
+            && game_state(&test_match.clients[0]) == GameState::Game
+    });
+    let game_data = game_data(&test_match.server);
+    let mut actors = [game_data.actor1, game_data.actor2];
+    actors.sort();
+    assert_eq!(actors, [host_id, remote_id]);
+
+    // The inputs of the host reach the simulation without a connection.
+    test_match.run_until("the host paddle is replicated", |test_match| {
+        paddle_position(&mut test_match.clients[0], host_id).is_some()
+    });
+    let start = paddle_position(&mut test_match.server, host_id).unwrap();
+    const INPUTS: u32 = 10;
+    for sequence in 1..=INPUTS {
+        test_match.server.world.send_event(MoveDirection {
+            direction: Vec2::Y,
+            sequence,
+        });
+        test_match.update();
+    }
+    let expected = start.y + INPUTS as f32 * rules.paddle_speed * TickRate::default().duration();
+    test_match.run_until(
+        "the host paddle moved for the remote client",
+        |test_match| {
+            paddle_position(&mut test_match.clients[0], host_id)
+                .is_some_and(|position| (position.y - expected).abs() < 0.01)
+        },
+    );
+}
+
+#[test]
+fn hot_seat_players_share_the_keyboard() {
+    let mut app = offline_app();
+    let host_id = SERVER_ID.raw();
+
+    // The match starts right away, keys held before don't reach the server.
+    app.world
+        .resource_mut::<Input<KeyCode>>()
+        .press(KeyCode::Up);
+    run_offline_until(&mut app, GameState::Game);
+    app.world
+        .resource_mut::<Input<KeyCode>>()
+        .release(KeyCode::Up);
+    assert_eq!(app.world.resource::<Violations>().count(GUEST_ID), 0.0);
+    let game_data = game_data(&app);
+    assert_eq!((game_data.actor1, game_data.actor2), (host_id, GUEST_ID));
+
+    let start = [host_id, GUEST_ID].map(|id| paddle_position(&mut app, id).unwrap());
+    let mut input = app.world.resource_mut::<Input<KeyCode>>();
+    input.press(KeyCode::W);
+    input.press(KeyCode::Down);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(paddle_position(&mut app, host_id).unwrap().y > start[0].y);
+    assert!(paddle_position(&mut app, GUEST_ID).unwrap().y < start[1].y);
+}
+
+#[test]
+fn hot_seat_guest_plays_every_match() {
+    let mut app = offline_app();
+    let host_id = SERVER_ID.raw();
+    run_offline_until(&mut app, GameState::Game);
+
+    // End the match and go back to the lobby, where "Start match" readies the host again.
+    let room_id = app.world.resource::<Rooms>().room_of(host_id).unwrap();
+    let mut rooms = app.world.resource_mut::<Rooms>();
+    rooms.get_mut(room_id).unwrap().game_data.phase = GameState::End;
+    run_offline_until(&mut app, GameState::End);
+    app.world.send_event(ClientMessage {
+        msg: C2sMessage::Leave,
+    });
+    for _ in 0..MAX_UPDATES {
+        if app.world.resource::<CurrentRoom>().0.is_none() {
+            break;
+        }
+        app.update();
+    }
+    let lobby = app.world.resource::<Lobby>();
+    assert!(lobby.contains(host_id) && lobby.contains(GUEST_ID));
+    app.world.send_event(ClientMessage {
+        msg: C2sMessage::SetReady(true),
+    });
+    run_offline_until(&mut app, GameState::Game);
+
+    let game_data = game_data(&app);
+    assert_eq!((game_data.actor1, game_data.actor2), (host_id, GUEST_ID));
+    let start = paddle_position(&mut app, GUEST_ID).unwrap();
+    app.world
+        .resource_mut::<Input<KeyCode>>()
+        .press(KeyCode::Up);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(paddle_position(&mut app, GUEST_ID).unwrap().y > start.y);
+}
+
+#[test]
+fn autopilot_plays_matches_back_to_back() {
+    // A fast ball and small paddles make sure the match doesn't wait for a long rally.
+    let rules = MatchRules {
+        win_condition: WinCondition::Rounds(1),
+        serve_delay_secs: 0.0,
+        ball_speed: 500.0,
+        paddle_size: Vec2::new(50.0, 10.0),
+        ..Default::default()
+    };
+    let mut test_match = TestMatch::connect(rules);
+    for client in &mut test_match.clients {
+        client.add_plugins(AutoPilotPlugin {
+            difficulty: BotDifficulty::Easy,
+        });
+    }
+    test_match.run_until("the first match started", |test_match| {
+        test_match
+            .clients
+            .iter()
+            .all(|client| client.world.resource::<CurrentRoom>().0.is_some())
+    });
+    let first_room = test_match.room_id();
+
+    test_match.run_until("both clients counted the match", |test_match| {
+        test_match
+            .clients
+            .iter()
+            .all(|client| client.world.resource::<AutoPilotStats>().played() == 1)
+    });
+    let stats: Vec<_> = test_match
+        .clients
+        .iter()
+        .map(|client| *client.world.resource::<AutoPilotStats>())
+        .collect();
+    assert_eq!(stats.iter().map(|stats| stats.won).sum::<u32>(), 1);
+    assert_eq!(stats.iter().map(|stats| stats.lost).sum::<u32>(), 1);
+
+    test_match.run_until("the next match started", |test_match| {
+        test_match.clients.iter().all(|client| {
+            client
+                .world
+                .resource::<CurrentRoom>()
+                .0
+                .is_some_and(|room_id| room_id != first_room)
+        })
+    });
+}
+
+#[test]
+fn match_is_recorded_and_played_back() {
+    let rules = MatchRules {
+        win_condition: WinCondition::Rounds(1),
+        serve_delay_secs: 0.0,
+        ..Default::default()
+    };
+    let dir = replay_dir("playback");
+    let mut test_match = TestMatch::connect(rules.clone());
+    test_match
+        .server
+        .insert_resource(ReplayRecorder::new(dir.clone()));
+    test_match.ready();
+
+    let client_id = TestMatch::CLIENT_IDS[0];
+    let start = paddle_position(&mut test_match.server, client_id)
+        .unwrap()
+        .y;
+    for sequence in 1..=10 {
+        test_match.clients[0].world.send_event(MoveDirection {
+            direction: Vec2::Y,
+            sequence,
+        });
+        test_match.update();
+    }
+    let expected = start + 10.0 * rules.paddle_speed * TickRate::default().duration();
+    test_match.run_until("the server applied all inputs", |test_match| {
+        paddle_position(&mut test_match.server, client_id)
+            .is_some_and(|position| (position.y - expected).abs() < 0.01)
+    });
+    let room_id = test_match.room_id();
+    let world = &mut test_match.server.world;
+    let mut balls =
+        world.query_filtered::<(&RoomId, &mut PlayerPosition, &mut PlayerSpeed), With<Ball>>();
+    for (_, mut position, mut velocity) in balls
+        .iter_mut(world)
+        .filter(|(ball_room_id, ..)| **ball_room_id == room_id)
+    {
+        **position = Vec2::new(1.0 - rules.ball_max().x, rules.ball_max().y);
+        **velocity = Vec2::new(-rules.ball_speed, 0.0);
+    }
+
+    test_match.run_until("the replay was saved", |_| !replay_files(&dir).is_empty());
+    let files = replay_files(&dir);
+    assert_eq!(files.len(), 1);
+    let replay = Replay::load(&files[0]).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut players: Vec<_> = replay
+        .players
+        .iter()
+        .map(|player| player.client_id)
+        .collect();
+    players.sort();
+    assert_eq!(players, TestMatch::CLIENT_IDS);
+    let last = replay.frames.last().unwrap();
+    let final_score = Score {
+        left: 0,
+        right: 1,
+        round: 1,
+    };
+    assert_eq!(last.score, final_score);
+    assert!(replay
+        .frames
+        .windows(2)
+        .all(|frames| frames[1].tick == frames[0].tick + 1));
+    let inputs = replay
+        .frames
+        .iter()
+        .flat_map(|frame| &frame.inputs)
+        .filter(|input| input.client_id == client_id && input.direction == Vec2::Y)
+        .count();
+    assert_eq!(inputs, 10);
+    let messages: Vec<_> = replay
+        .frames
+        .iter()
+        .flat_map(|frame| frame.messages.clone())
+        .collect();
+    assert!(matches!(messages.first(), Some(S2cMessage::GameStart(..))));
+    assert!(messages.contains(&S2cMessage::RoundResult(2)));
+    assert_eq!(messages.last(), Some(&S2cMessage::GameEnd));
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        ReplicationPlugins.build().set(ServerPlugin {
+            tick_policy: TickPolicy::Manual,
+            ..Default::default()
+        }),
+        PingPongSharedPlugin,
+    ));
+    add_client_plugins(&mut app);
+    app.add_plugins(ReplayPlaybackPlugin {
+        replay: replay.clone(),
+    });
+    step_time(&mut app, TickRate::default());
+    app.update();
+    app.update();
+    assert!(game_data(&app).is_spectator(app.world.resource::<LocalData>().client_id));
+
+    let index = app.world.resource::<ReplayPlayback>().frame_index();
+    let frame = &replay.frames[index];
+    assert!(index > 0);
+    assert_eq!(
+        paddle_position(&mut app, replay.players[0].client_id),
+        Some(frame.paddles[0])
+    );
+
+    app.world.resource_mut::<ReplayPlayback>().seek(usize::MAX);
+    app.update();
+    assert_eq!(replicated_score(&mut app), Some(final_score));
+    assert!(app.world.resource::<ReplayPlayback>().paused);
+}
+
+#[test]
+fn forfeit_against_a_bot_is_recorded() {
+    let rules = MatchRules {
+        reconnect_grace_secs: 0.5,
+        ..Default::default()
+    };
+    let dir = replay_dir("bot-forfeit");
+    let mut test_match = TestMatch::connect(rules);
+    test_match
+        .server
+        .insert_resource(ReplayRecorder::new(dir.clone()));
+    let client_id = TestMatch::CLIENT_IDS[0];
+    test_match.clients[0].world.send_event(ClientMessage {
+        msg: C2sMessage::PlayBot(BotDifficulty::Easy),
+    });
+    test_match.run_until("the match against the bot started", |test_match| {
+        game_state(&test_match.clients[0]) == GameState::Game
+    });
+
+    // Messages are recorded as they were sent, repeats included.
+    let room_id = test_match.room_id();
+    for _ in 0..2 {
+        test_match.server.world.send_event(RoomMessage {
+            room_id,
+            msg: S2cMessage::RematchVote(client_id),
+        });
+    }
+    test_match.update();
+    // Only the bot is left to receive the messages about the forfeit.
+    test_match.clients[0]
+        .world
+        .resource_mut::<NetcodeClientTransport>()
+        .disconnect();
+    test_match.run_until("the replay was saved", |_| !replay_files(&dir).is_empty());
+    let replay = Replay::load(&replay_files(&dir)[0]).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let messages: Vec<_> = replay
+        .frames
+        .iter()
+        .flat_map(|frame| frame.messages.clone())
+        .collect();
+    let votes = messages
+        .iter()
+        .filter(|msg| **msg == S2cMessage::RematchVote(client_id))
+        .count();
+    assert_eq!(votes, 2);
+    assert!(messages.contains(&S2cMessage::PlayerDisconnected(client_id)));
+    let forfeit = messages
+        .iter()
+        .position(|msg| *msg == S2cMessage::Forfeit(FIRST_BOT_ID))
+        .unwrap();
+    assert_eq!(messages.get(forfeit + 1), Some(&S2cMessage::GameEnd));
+}
+
+#[test]
+fn recorded_match_is_simulated_identically() {
+    // Several fast rounds with rallies exercise the bounces, the spin and the seeded serves.
+    let rules = MatchRules {
+        win_condition: WinCondition::Rounds(3),
+        serve_delay_secs: 0.0,
+        ball_speed: 500.0,
+        paddle_size: Vec2::new(50.0, 20.0),
+        ..Default::default()
+    };
+    let dir = replay_dir("verify");
+    let mut test_match = TestMatch::connect(rules);
+    test_match
+        .server
+        .insert_resource(ReplayRecorder::new(dir.clone()));
+    for client in &mut test_match.clients {
+        client.add_plugins(AutoPilotPlugin {
+            difficulty: BotDifficulty::Easy,
+        });
+    }
+    // Easy autopilots miss most balls, but now and then a rally takes a while.
+    test_match.run_until_within(10 * MAX_UPDATES, "the replay was saved", |_| {
+        !replay_files(&dir).is_empty()
+    });
+    let mut replay = Replay::load(&replay_files(&dir)[0]).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let last = replay.frames.last().unwrap();
+    assert_eq!(last.score.round, 3);
+    assert_eq!(verify(&replay).unwrap(), replay.frames.len());
+
+    // A single changed input moves a paddle somewhere else than recorded.
+    let input = replay
+        .frames
+        .iter_mut()
+        .flat_map(|frame| &mut frame.inputs)
+        .next()
+        .expect("the autopilots should have moved");
+    input.direction = -input.direction;
+    assert!(verify(&replay).is_err());
+}
+
+#[test]
+fn invalid_inputs_are_clamped_and_counted() {
+    let rules = MatchRules::default();
+    let mut test_match = TestMatch::connect(rules.clone());
+    let client_id = TestMatch::CLIENT_IDS[0];
+    let violations = |test_match: &TestMatch| {
+        test_match
+            .server
+            .world
+            .resource::<Violations>()
+            .count(client_id)
+    };
+
+    // Nobody plays in the lobby.
+    test_match.clients[0].world.send_event(MoveDirection {
+        direction: Vec2::Y,
+        sequence: 1,
+    });
+    test_match.run_until("the lobby input was counted", |test_match| {
+        violations(test_match) > 0.0
+    });
+
+    test_match.ready();
+    test_match.run_until("the paddle is spawned", |test_match| {
+        paddle_position(&mut test_match.server, client_id).is_some()
+    });
+    let start = paddle_position(&mut test_match.server, client_id).unwrap();
+    test_match.clients[0].world.send_event(MoveDirection {
+        direction: Vec2::new(500.0, 1000.0),
+        sequence: 2,
+    });
+    test_match.run_until("the paddle moved", |test_match| {
+        paddle_position(&mut test_match.server, client_id) != Some(start)
+    });
+
+    // The paddle moves straight up at its normal speed.
+    let position = paddle_position(&mut test_match.server, client_id).unwrap();
+    let step = rules.paddle_speed * TickRate::default().duration();
+    assert_eq!(position.x, start.x);
+    assert!((position.y - start.y - step).abs() < 0.01);
+    assert!(violations(&test_match) > 1.0);
+}
+
+#[test]
+fn flooding_client_is_kicked() {
+    let mut test_match = TestMatch::start(MatchRules::default());
+    test_match
+        .server
+        .insert_resource(ValidationSettings { max_violations: 5 });
+
+    // A client sends one input per tick, these are way too many.
+    for sequence in 1..=50 {
+        test_match.clients[0].world.send_event(MoveDirection {
+            direction: Vec2::Y,
+            sequence,
+        });
+    }
+    test_match.run_until("the client was disconnected", |test_match| {
+        test_match.clients[0]
+            .world
+            .resource::<RenetClient>()
+            .is_disconnected()
+    });
+    test_match.run_until("the match is paused", |test_match| {
+        game_state(&test_match.clients[1]) == GameState::Paused
+    });
+}
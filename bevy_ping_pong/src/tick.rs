@@ -0,0 +1,43 @@
+//! Fixed-rate server simulation.
+//!
+//! The server simulates all matches in `FixedUpdate` at its [`TickRate`] and increments the
+//! [`RepliconTick`](bevy_replicon::prelude::RepliconTick) once per simulated tick, so every
+//! replication message is stamped with the number of the tick it describes. This requires the
+//! server to add the replicon `ServerPlugin` with `TickPolicy::Manual`.
+//!
+//! The last tick and the rate are replicated in [`SimulationTick`]. Clients take over the rate
+//! to sample their inputs once per tick and use the tick number to timestamp snapshots.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Simulation ticks per second.
+#[derive(Clone, Copy, Debug, Deref, PartialEq, Resource)]
+pub struct TickRate(pub u16);
+
+impl Default for TickRate {
+    fn default() -> Self {
+        Self(60)
+    }
+}
+
+impl TickRate {
+    /// Simulated time of a single tick in seconds.
+    pub fn duration(self) -> f32 {
+        1.0 / self.0 as f32
+    }
+}
+
+/// The last simulated server tick.
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Serialize)]
+pub struct SimulationTick {
+    pub tick: u32,
+    pub rate: u16,
+}
+
+impl SimulationTick {
+    /// Simulated time at the end of the tick in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.tick as f64 / self.rate as f64
+    }
+}
@@ -0,0 +1,190 @@
+//! Validation of the inputs clients send to the server.
+//!
+//! The server doesn't trust the [`MoveDirection`](crate::MoveDirection)s it receives: a direction
+//! is clamped to a vertical unit, so no input moves a paddle sideways or faster than its speed,
+//! and directions that aren't numbers are dropped. A client gets one input applied per tick and
+//! can't queue more than a few ticks ahead, and inputs of clients that don't play a match are
+//! ignored.
+//!
+//! Every corrected or dropped input counts as a [`Violation`] of its client. Honest clients only
+//! cause them now and then, for example when a lag spike delivers a burst of inputs, so violations
+//! are forgiven over time. A client whose count exceeds the `max_violations` of the
+//! [`ValidationSettings`] is disconnected.
+
+use std::fmt;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::{prelude::*, renet::ClientId};
+
+/// Violations forgiven per second of every client.
+const FORGIVEN_PER_SEC: f32 = 0.1;
+
+/// Ways a client can break the input rules.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Violation {
+    /// The direction wasn't a vertical unit, or not a number at all.
+    InvalidDirection,
+    /// More inputs arrived than ticks were simulated.
+    TooManyInputs,
+    /// The client doesn't play a match.
+    NotPlaying,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Self::InvalidDirection => "sent an invalid direction",
+            Self::TooManyInputs => "sent more inputs than ticks",
+            Self::NotPlaying => "sent an input without playing",
+        };
+        f.write_str(description)
+    }
+}
+
+#[derive(Resource)]
+pub struct ValidationSettings {
+    /// Violations a client may have before it's disconnected.
+    pub max_violations: u32,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        Self { max_violations: 20 }
+    }
+}
+
+/// Recent violations of every client, minus the forgiven ones.
+#[derive(Resource, Default)]
+pub struct Violations(HashMap<u64, f32>);
+
+impl Violations {
+    pub fn count(&self, client_id: u64) -> f32 {
+        self.0.get(&client_id).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn record(&mut self, client_id: u64, violation: Violation) {
+        let count = self.0.entry(client_id).or_default();
+        *count += 1.0;
+        warn!("client {client_id} {violation}, {count:.1} recent violations");
+    }
+}
+
+/// Clamps a direction to a vertical unit, `None` if it isn't a number.
+pub(crate) fn sanitize_direction(direction: Vec2) -> Option<Vec2> {
+    if !direction.is_finite() {
+        return None;
+    }
+
+    Some(Vec2::new(0.0, direction.y.clamp(-1.0, 1.0)))
+}
+
+pub(crate) fn forgive_system(time: Res<Time>, mut violations: ResMut<Violations>) {
+    let forgiven = time.delta_seconds() * FORGIVEN_PER_SEC;
+    violations.0.retain(|_, count| {
+        *count -= forgiven;
+        *count > 0.0
+    });
+}
+
+/// Disconnects the clients with too many violations.
+pub(crate) fn kick_system(
+    settings: Res<ValidationSettings>,
+    mut violations: ResMut<Violations>,
+    mut server: ResMut<RenetServer>,
+) {
+    violations.0.retain(|&client_id, count| {
+        // The host plays on its own server.
+        if *count <= settings.max_violations as f32 || client_id == SERVER_ID.raw() {
+            return true;
+        }
+        warn!("disconnecting client {client_id} after {count:.0} input violations");
+        server.disconnect(ClientId::from_raw(client_id));
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_replicon::renet::{ConnectionConfig, DisconnectReason};
+
+    use super::*;
+
+    #[test]
+    fn directions_are_clamped_to_a_vertical_unit() {
+        assert_eq!(sanitize_direction(Vec2::Y), Some(Vec2::Y));
+        assert_eq!(
+            sanitize_direction(Vec2::new(0.0, -0.5)),
+            Some(Vec2::new(0.0, -0.5))
+        );
+        assert_eq!(sanitize_direction(Vec2::new(0.0, 5.0)), Some(Vec2::Y));
+        assert_eq!(sanitize_direction(Vec2::new(3.0, -7.0)), Some(Vec2::NEG_Y));
+        assert_eq!(sanitize_direction(Vec2::X), Some(Vec2::ZERO));
+    }
+
+    #[test]
+    fn non_numeric_directions_are_dropped() {
+        assert_eq!(sanitize_direction(Vec2::new(0.0, f32::NAN)), None);
+        assert_eq!(sanitize_direction(Vec2::new(f32::NAN, 0.0)), None);
+        assert_eq!(sanitize_direction(Vec2::new(0.0, f32::INFINITY)), None);
+        assert_eq!(sanitize_direction(Vec2::new(f32::NEG_INFINITY, 1.0)), None);
+    }
+
+    #[test]
+    fn violations_are_forgiven_over_time() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        let mut violations = Violations::default();
+        violations.record(1, Violation::TooManyInputs);
+        violations.record(1, Violation::TooManyInputs);
+        world.insert_resource(violations);
+
+        let advance = |world: &mut World, secs| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs(secs));
+            world.run_system_once(forgive_system);
+        };
+        advance(&mut world, 5);
+        assert!((world.resource::<Violations>().count(1) - 1.5).abs() < 1e-4);
+        advance(&mut world, 10);
+        assert!((world.resource::<Violations>().count(1) - 0.5).abs() < 1e-4);
+        advance(&mut world, 10);
+        assert!(world.resource::<Violations>().0.is_empty());
+    }
+
+    #[test]
+    fn clients_over_the_limit_are_kicked() {
+        let mut world = World::new();
+        let mut server = RenetServer::new(ConnectionConfig::default());
+        let (over, at_limit) = (ClientId::from_raw(1), ClientId::from_raw(2));
+        server.add_connection(over);
+        server.add_connection(at_limit);
+        world.insert_resource(server);
+        world.insert_resource(ValidationSettings { max_violations: 2 });
+        let mut violations = Violations::default();
+        for _ in 0..3 {
+            violations.record(over.raw(), Violation::InvalidDirection);
+            violations.record(SERVER_ID.raw(), Violation::InvalidDirection);
+        }
+        for _ in 0..2 {
+            violations.record(at_limit.raw(), Violation::InvalidDirection);
+        }
+        world.insert_resource(violations);
+
+        world.run_system_once(kick_system);
+        let server = world.resource::<RenetServer>();
+        assert_eq!(
+            server.disconnect_reason(over),
+            Some(DisconnectReason::DisconnectedByServer)
+        );
+        assert_eq!(server.disconnect_reason(at_limit), None);
+        let violations = world.resource::<Violations>();
+        assert_eq!(violations.count(over.raw()), 0.0);
+        assert_eq!(violations.count(at_limit.raw()), 2.0);
+        // The host isn't kicked from its own server.
+        assert_eq!(violations.count(SERVER_ID.raw()), 3.0);
+    }
+}
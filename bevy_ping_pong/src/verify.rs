@@ -0,0 +1,209 @@
+//! Deterministic re-simulation of recorded matches.
+//!
+//! [`verify`] plays the recorded inputs of a [`Replay`] into a headless server without any
+//! network, starting from the serve and the seed of the recorded match, and compares every
+//! simulated tick with the recorded frame. The simulation is deterministic, so any difference in
+//! the positions of the ball and the paddles or in the score means it changed since the replay was
+//! recorded, or that it depends on something besides the inputs and the seed.
+//!
+//! A directory of replays thereby serves as a regression corpus for the physics, the `verify`
+//! binary checks all of them at once.
+
+use std::{error::Error, time::Duration};
+
+use bevy::{ecs::system::CommandQueue, prelude::*, time::TimeUpdateStrategy};
+use bevy_replicon::{prelude::*, renet::ClientId, server::TickPolicy};
+
+use crate::{
+    player_color,
+    replay::{Replay, ReplayFrame},
+    room::{RoomId, Rooms},
+    scoring::Score,
+    tick::TickRate,
+    Ball, GameState, InputQueues, MoveDirection, Paddle, PingPongServerPlugin,
+    PingPongSharedPlugin, Player, PlayerBundle, PlayerPosition,
+};
+
+/// Updates waited for a single tick before the simulation is considered stuck.
+const MAX_UPDATES_PER_TICK: usize = 3;
+
+/// Re-simulates the match of a replay and returns the number of verified ticks.
+///
+/// Fails with a description of the first tick that doesn't match its recorded frame.
+pub fn verify(replay: &Replay) -> Result<usize, Box<dyn Error>> {
+    let first = replay
+        .frames
+        .first()
+        .ok_or("the replay contains no frames")?;
+    let mut app = simulation_app(replay);
+    let room_id = open_room(&mut app.world, replay, first);
+
+    for (index, frame) in replay.frames.iter().enumerate() {
+        let world = &mut app.world;
+        let mut rooms = world.resource_mut::<Rooms>();
+        let game_data = &mut rooms
+            .get_mut(room_id)
+            .expect("simulated room should exist")
+            .game_data;
+        if game_data.phase == GameState::End {
+            return Err(format!(
+                "the match ended at tick {} but {} more ticks were recorded",
+                frame.tick - 1,
+                replay.frames.len() - index
+            )
+            .into());
+        }
+        // Disconnects pause the match between two ticks.
+        game_data.phase = if frame.paused {
+            GameState::Paused
+        } else {
+            GameState::Game
+        };
+
+        let mut queues = world.resource_mut::<InputQueues>();
+        for input in &frame.inputs {
+            queues
+                .entry(ClientId::from_raw(input.client_id))
+                .or_default()
+                .push_back(MoveDirection {
+                    direction: input.direction,
+                    sequence: index as u32,
+                });
+        }
+
+        // The clock of the first update doesn't advance yet.
+        for _ in 0..MAX_UPDATES_PER_TICK {
+            if app.world.resource::<RepliconTick>().get() >= frame.tick {
+                break;
+            }
+            app.update();
+        }
+        let tick = app.world.resource::<RepliconTick>().get();
+        if tick != frame.tick {
+            return Err(format!("simulated tick {tick} instead of {}", frame.tick).into());
+        }
+
+        compare(&mut app.world, replay, room_id, frame)
+            .map_err(|e| format!("tick {} ({:.2} s): {e}", frame.tick, replay.seconds(index)))?;
+    }
+
+    Ok(replay.frames.len())
+}
+
+/// A server that simulates one tick per update.
+fn simulation_app(replay: &Replay) -> App {
+    let timestep = Duration::from_secs_f64(1.0 / replay.tick_rate as f64);
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        ReplicationPlugins.build().set(ServerPlugin {
+            tick_policy: TickPolicy::Manual,
+            visibility_policy: VisibilityPolicy::Whitelist,
+            ..Default::default()
+        }),
+        PingPongSharedPlugin,
+        PingPongServerPlugin,
+    ))
+    .init_resource::<Rooms>()
+    .insert_resource(replay.rules.clone())
+    .insert_resource(TickRate(replay.tick_rate))
+    .insert_resource(Time::<Fixed>::from_duration(timestep))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(timestep));
+
+    app
+}
+
+/// Opens the room of the recorded match like the matchmaking does.
+///
+/// The match starts in the tick before the first frame, with the serve and the seed of the
+/// replay.
+fn open_room(world: &mut World, replay: &Replay, first: &ReplayFrame) -> RoomId {
+    world
+        .resource_mut::<RepliconTick>()
+        .increment_by(first.tick.saturating_sub(1));
+
+    let mut queue = CommandQueue::default();
+    let room_id = world.resource_scope(|world, mut rooms: Mut<Rooms>| {
+        let mut commands = Commands::new(&mut queue, world);
+        let room_id = rooms.open_with_seed(&mut commands, &replay.rules, first.serve, replay.seed);
+        for (player, x) in replay
+            .players
+            .iter()
+            .zip([-replay.rules.paddle_x(), replay.rules.paddle_x()])
+        {
+            commands.spawn(PlayerBundle::new(
+                ClientId::from_raw(player.client_id),
+                room_id,
+                player.name.clone(),
+                Vec2::new(x, 0.0),
+                player_color(player.client_id),
+            ));
+            rooms.join(player.client_id, room_id);
+        }
+
+        let game_data = &mut rooms
+            .get_mut(room_id)
+            .expect("opened room should exist")
+            .game_data;
+        let [left, right] = &replay.players;
+        game_data.actor1 = left.client_id;
+        game_data.actor2 = right.client_id;
+        game_data.player_count = 2;
+
+        room_id
+    });
+    queue.apply(world);
+
+    room_id
+}
+
+/// Checks the simulated state against a recorded frame.
+fn compare(
+    world: &mut World,
+    replay: &Replay,
+    room_id: RoomId,
+    frame: &ReplayFrame,
+) -> Result<(), String> {
+    let ball = world
+        .query_filtered::<(&RoomId, &PlayerPosition), With<Ball>>()
+        .iter(world)
+        .find(|(id, _)| **id == room_id)
+        .map(|(_, position)| **position)
+        .expect("simulated room should have a ball");
+    if ball != frame.ball {
+        return Err(format!(
+            "ball at {ball} instead of the recorded {}",
+            frame.ball
+        ));
+    }
+
+    for (player, recorded) in replay.players.iter().zip(frame.paddles) {
+        let paddle = world
+            .query_filtered::<(&Player, &PlayerPosition), With<Paddle>>()
+            .iter(world)
+            .find(|(paddle_player, _)| paddle_player.0.raw() == player.client_id)
+            .map(|(_, position)| **position)
+            .expect("simulated room should have both paddles");
+        if paddle != recorded {
+            return Err(format!(
+                "paddle of {} at {paddle} instead of the recorded {recorded}",
+                player.name
+            ));
+        }
+    }
+
+    let score = world
+        .query::<(&RoomId, &Score)>()
+        .iter(world)
+        .find(|(id, _)| **id == room_id)
+        .map(|(_, score)| *score)
+        .expect("simulated room should have a score");
+    if score != frame.score {
+        return Err(format!(
+            "score {score:?} instead of the recorded {:?}",
+            frame.score
+        ));
+    }
+
+    Ok(())
+}